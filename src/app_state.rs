@@ -3,11 +3,21 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::ongoing_round_protection::{OngoingRoundProtection, OngoingRoundProtectionConfig};
+use eyre::Context;
+
+use crate::{
+    cache::{Cache, CacheConfig},
+    ongoing_round_protection::{OngoingRoundProtection, OngoingRoundProtectionConfig},
+    parsers::rules::Rules,
+    search::{SearchConfig, SearchIndex},
+};
 
 #[derive(Debug)]
 pub struct AppState {
     pub config: Config,
+    pub rules: Rules,
+    pub cache: Option<Cache>,
+    pub search: Option<SearchIndex>,
     ongoing_round_protection: OngoingRoundProtection,
 }
 
@@ -16,7 +26,27 @@ impl AppState {
         let mut config: Config = toml::from_str(&std::fs::read_to_string("config.toml")?)?;
         config.raw_logs_path = config.raw_logs_path.canonicalize()?;
 
+        let rules = Rules::load(Path::new("rules.toml")).context("loading rules")?;
+
+        let cache = match config.cache.take() {
+            Some(cache_config) => {
+                Some(Cache::new(cache_config, rules.policy_version()).context("loading cache")?)
+            }
+            None => None,
+        };
+
+        let search = match config.search.take() {
+            Some(search_config) => Some(
+                SearchIndex::new(search_config, rules.policy_version())
+                    .context("loading search index")?,
+            ),
+            None => None,
+        };
+
         Ok(AppState {
+            rules,
+            cache,
+            search,
             ongoing_round_protection: OngoingRoundProtection::new(
                 config.ongoing_round_protection.take().unwrap(),
             ),
@@ -37,6 +67,10 @@ pub struct Config {
     pub address: SocketAddr,
     pub raw_logs_path: PathBuf,
     ongoing_round_protection: Takeable<OngoingRoundProtectionConfig>,
+    #[serde(default)]
+    cache: Option<CacheConfig>,
+    #[serde(default)]
+    search: Option<SearchConfig>,
 }
 
 #[derive(Debug)]
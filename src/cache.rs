@@ -0,0 +1,573 @@
+use std::{
+    convert::Infallible,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::UNIX_EPOCH,
+};
+
+use axum::{
+    body::Body,
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, VARY},
+        HeaderMap, HeaderValue,
+    },
+    response::{IntoResponseParts, ResponseParts},
+};
+use eyre::Context;
+use sha2::{Digest, Sha256};
+
+use crate::{app_state::AppState, streaming::stream_body};
+
+const TEMPORARY_EXTENSION: &str = "tmp";
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CacheConfig {
+    path: PathBuf,
+
+    // Once the cache is bigger than this, the oldest entries are removed
+    max_size_bytes: u64,
+}
+
+// Sanitized outputs on disk. Finished rounds never change, so these only go stale when the
+// sanitization policy does, and every policy version gets its own directory.
+#[derive(Debug)]
+pub struct Cache {
+    directory: PathBuf,
+    max_size_bytes: u64,
+
+    eviction_lock: parking_lot::Mutex<()>,
+    temporary_counter: AtomicU64,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig, policy_version: &str) -> eyre::Result<Self> {
+        std::fs::create_dir_all(&config.path)
+            .with_context(|| format!("creating {}", config.path.display()))?;
+
+        // Anything from an older policy will never be used again
+        for entry in std::fs::read_dir(&config.path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            if entry.file_type()?.is_dir()
+                && name != policy_version
+                && name.len() == policy_version.len()
+                && name.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                tracing::info!("removing cache for old policy version {name}");
+                std::fs::remove_dir_all(entry.path())
+                    .with_context(|| format!("removing old cache {name}"))?;
+            }
+        }
+
+        let directory = config.path.join(policy_version);
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            max_size_bytes: config.max_size_bytes,
+            eviction_lock: parking_lot::Mutex::new(()),
+            temporary_counter: AtomicU64::new(0),
+        })
+    }
+
+    // The variant is anything else that changes the output, like which pretend file it is.
+    pub fn key(&self, source_path: &Path, variant: &str) -> io::Result<CacheKey> {
+        let metadata = std::fs::metadata(source_path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut hasher = Sha256::new();
+        hasher.update(source_path.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update(modified.as_nanos().to_le_bytes());
+        hasher.update(metadata.len().to_le_bytes());
+        hasher.update(variant);
+
+        Ok(CacheKey {
+            path: self.directory.join(format!("{:x}", hasher.finalize())),
+        })
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<File> {
+        File::open(&key.path).ok()
+    }
+
+    pub fn get_encoded(&self, key: &CacheKey, encoding: Encoding) -> Option<File> {
+        File::open(key.path.with_extension(encoding.extension())).ok()
+    }
+
+    pub fn create(&self, key: &CacheKey) -> io::Result<CacheEntryWriter<'_>> {
+        self.create_at(key.path.clone())
+    }
+
+    fn create_at(&self, path: PathBuf) -> io::Result<CacheEntryWriter<'_>> {
+        let temporary_path = path.with_extension(format!(
+            "{}.{TEMPORARY_EXTENSION}",
+            self.temporary_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        Ok(CacheEntryWriter {
+            cache: self,
+            file: io::BufWriter::new(
+                File::options()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&temporary_path)?,
+            ),
+            temporary_path,
+            path,
+        })
+    }
+
+    // Compresses a finished entry ahead of time, so clients that accept it don't need it
+    // compressed on every request.
+    pub fn write_encoded(&self, key: &CacheKey) -> io::Result<()> {
+        for encoding in ENCODINGS {
+            let mut entry = self.create_at(key.path.with_extension(encoding.extension()))?;
+            encoding.compress(&mut io::BufReader::new(File::open(&key.path)?), &mut entry)?;
+            entry.finish()?;
+        }
+
+        Ok(())
+    }
+
+    fn evict(&self) -> io::Result<()> {
+        let _guard = self.eviction_lock.lock();
+
+        let mut entries = Vec::new();
+        let mut total_size = 0;
+
+        for entry in std::fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            // Could be another entry being written right now
+            if path
+                .extension()
+                .is_some_and(|extension| extension.to_string_lossy().ends_with(TEMPORARY_EXTENSION))
+            {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            total_size += metadata.len();
+            entries.push((metadata.modified()?, metadata.len(), path));
+        }
+
+        if total_size <= self.max_size_bytes {
+            return Ok(());
+        }
+
+        entries.sort();
+
+        for (_, size, path) in entries {
+            if total_size <= self.max_size_bytes {
+                break;
+            }
+
+            tracing::debug!("evicting {}", path.display());
+            match std::fs::remove_file(&path) {
+                Ok(()) => total_size -= size,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => total_size -= size,
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheKey {
+    path: PathBuf,
+}
+
+// Content codings that entries are also kept in, in order of preference
+const ENCODINGS: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zst",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    // These are done once per entry, so they can afford to be slower than compressing on the fly
+    fn compress(self, reader: &mut impl Read, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(writer, 64 * 1024, 9, 22);
+                io::copy(reader, &mut encoder)?;
+                encoder.flush()
+            }
+
+            Encoding::Zstd => zstd::stream::copy_encode(reader, writer, 12),
+
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(writer, flate2::Compression::best());
+                io::copy(reader, &mut encoder)?;
+                encoder.finish().map(|_| ())
+            }
+        }
+    }
+}
+
+// The encodings the client accepts, most wanted first
+fn accepted_encodings(headers: &HeaderMap) -> Vec<Encoding> {
+    // A coding named outright takes precedence over *, so "*, br;q=0" still refuses br
+    let mut qualities: [Option<f32>; ENCODINGS.len()] = [None; ENCODINGS.len()];
+    let mut wildcard_quality = None;
+
+    for value in headers.get_all(ACCEPT_ENCODING) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+
+        for item in value.split(',') {
+            let mut parameters = item.split(';');
+            let coding = parameters.next().unwrap_or_default().trim();
+            let quality: f32 = parameters
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .and_then(|quality| quality.parse().ok())
+                .unwrap_or(1.0);
+
+            if coding == "*" {
+                wildcard_quality = Some(quality);
+            } else if let Some(index) = ENCODINGS
+                .iter()
+                .position(|encoding| coding.eq_ignore_ascii_case(encoding.name()))
+            {
+                qualities[index] = Some(quality);
+            }
+        }
+    }
+
+    let mut accepted: Vec<(f32, Encoding)> = ENCODINGS
+        .into_iter()
+        .zip(qualities)
+        .filter_map(|(encoding, quality)| Some((quality.or(wildcard_quality)?, encoding)))
+        .filter(|(quality, _)| *quality > 0.0)
+        .collect();
+
+    // Stable, so ties stay in order of preference
+    accepted.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    accepted.into_iter().map(|(_, encoding)| encoding).collect()
+}
+
+// Sets Content-Encoding for bodies that were already compressed in the cache
+pub struct ContentEncoding(Option<Encoding>);
+
+impl IntoResponseParts for ContentEncoding {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        if let Some(encoding) = self.0 {
+            res.headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+            res.headers_mut()
+                .append(VARY, HeaderValue::from_static("accept-encoding"));
+        }
+
+        Ok(res)
+    }
+}
+
+// Writes to a temporary file, which only becomes the entry once it's finished.
+// Dropping this without finishing it throws the output away.
+pub struct CacheEntryWriter<'a> {
+    cache: &'a Cache,
+    file: io::BufWriter<File>,
+    temporary_path: PathBuf,
+    path: PathBuf,
+}
+
+impl CacheEntryWriter<'_> {
+    // Returns the finished entry to read from, which still works even if it gets evicted right away
+    pub fn finish(mut self) -> io::Result<File> {
+        self.file.flush()?;
+
+        let mut file = self.file.get_ref().try_clone()?;
+        file.seek(SeekFrom::Start(0))?;
+
+        std::fs::rename(&self.temporary_path, &self.path)?;
+
+        if let Err(error) = self.cache.evict() {
+            tracing::error!("error evicting cache entries: {error:?}");
+        }
+
+        Ok(file)
+    }
+}
+
+impl Write for CacheEntryWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for CacheEntryWriter<'_> {
+    fn drop(&mut self) {
+        // Does nothing if it was renamed by finish
+        let _ = std::fs::remove_file(&self.temporary_path);
+    }
+}
+
+// Writes everything to both the client and the cache entry
+struct TeeWriter<'a, 'b> {
+    client: &'a mut dyn Write,
+    cache_entry: &'a mut CacheEntryWriter<'b>,
+}
+
+impl Write for TeeWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.cache_entry.write_all(buf)?;
+        self.client.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.cache_entry.flush()?;
+        self.client.flush()
+    }
+}
+
+// Streams whatever the writing function writes, going through the cache if there is one.
+// The variant is anything other than the source file that changes the output.
+pub fn stream_cached_body<F>(
+    state: Arc<AppState>,
+    request_headers: &HeaderMap,
+    source_path: PathBuf,
+    variant: String,
+    write_fn: F,
+) -> (ContentEncoding, Body)
+where
+    F: FnOnce(&AppState, &mut dyn Write) -> io::Result<()> + Send + 'static,
+{
+    let Some(cache) = &state.cache else {
+        return stream_uncached_body(state, write_fn);
+    };
+
+    let key = match cache.key(&source_path, &variant) {
+        Ok(key) => key,
+        Err(error) => {
+            tracing::error!(
+                "couldn't get cache key for {}: {error:?}",
+                source_path.display()
+            );
+            return stream_uncached_body(state, write_fn);
+        }
+    };
+
+    for encoding in accepted_encodings(request_headers) {
+        if let Some(mut cached) = cache.get_encoded(&key, encoding) {
+            tracing::debug!("{encoding:?} cache hit for {}", source_path.display());
+            return (
+                ContentEncoding(Some(encoding)),
+                stream_body(move |writer| io::copy(&mut cached, writer).map(|_| ())),
+            );
+        }
+    }
+
+    if let Some(mut cached) = cache.get(&key) {
+        tracing::debug!("cache hit for {}", source_path.display());
+        return (
+            ContentEncoding(None),
+            stream_body(move |writer| io::copy(&mut cached, writer).map(|_| ())),
+        );
+    }
+
+    (
+        ContentEncoding(None),
+        stream_body(move |client| {
+            let cache = state.cache.as_ref().expect("cache went away");
+
+            let mut cache_entry = cache.create(&key)?;
+            write_fn(
+                &state,
+                &mut TeeWriter {
+                    client,
+                    cache_entry: &mut cache_entry,
+                },
+            )?;
+            cache_entry.finish()?;
+
+            write_encoded_in_background(Arc::clone(&state), key);
+
+            Ok(())
+        }),
+    )
+}
+
+// For outputs not worth keeping around, like ones narrowed down by arbitrary query values
+pub fn stream_uncached_body<F>(state: Arc<AppState>, write_fn: F) -> (ContentEncoding, Body)
+where
+    F: FnOnce(&AppState, &mut dyn Write) -> io::Result<()> + Send + 'static,
+{
+    (
+        ContentEncoding(None),
+        stream_body(move |writer| write_fn(&state, writer)),
+    )
+}
+
+// Not holding up the end of the response for this
+fn write_encoded_in_background(state: Arc<AppState>, key: CacheKey) {
+    tokio::task::spawn_blocking(move || {
+        let cache = state.cache.as_ref().expect("cache went away");
+        if let Err(error) = cache.write_encoded(&key) {
+            tracing::error!("couldn't compress cache entry: {error:?}");
+        }
+    });
+}
+
+// All of an output, for when its length needs to be known before sending any of it
+pub struct CompleteOutput {
+    pub length: u64,
+    contents: CompleteContents,
+}
+
+enum CompleteContents {
+    Cached(File),
+    Generated(Vec<u8>),
+}
+
+impl CompleteOutput {
+    pub fn into_body(self, range: RangeInclusive<u64>) -> Body {
+        let (start, end) = range.into_inner();
+
+        match self.contents {
+            CompleteContents::Cached(mut file) => stream_body(move |writer| {
+                file.seek(SeekFrom::Start(start))?;
+                io::copy(&mut file.take(end - start + 1), writer).map(|_| ())
+            }),
+
+            CompleteContents::Generated(mut contents) => {
+                contents.truncate(end as usize + 1);
+                contents.drain(..start as usize);
+                Body::from(contents)
+            }
+        }
+    }
+
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self.contents {
+            CompleteContents::Cached(mut file) => {
+                let mut contents = Vec::with_capacity(self.length as usize);
+                file.seek(SeekFrom::Start(0))?;
+                file.read_to_end(&mut contents)?;
+                Ok(contents)
+            }
+
+            CompleteContents::Generated(contents) => Ok(contents),
+        }
+    }
+}
+
+// Like stream_cached_body, but writes out everything first. Used for range requests.
+pub async fn complete_output<F>(
+    state: Arc<AppState>,
+    source_path: PathBuf,
+    variant: String,
+    write_fn: F,
+) -> io::Result<CompleteOutput>
+where
+    F: FnOnce(&AppState, &mut dyn Write) -> io::Result<()> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let contents = match &state.cache {
+            Some(cache) => {
+                let key = cache.key(&source_path, &variant)?;
+
+                match cache.get(&key) {
+                    Some(cached) => CompleteContents::Cached(cached),
+                    None => {
+                        let mut cache_entry = cache.create(&key)?;
+                        write_fn(&state, &mut cache_entry)?;
+                        let file = cache_entry.finish()?;
+
+                        write_encoded_in_background(Arc::clone(&state), key);
+
+                        CompleteContents::Cached(file)
+                    }
+                }
+            }
+
+            None => {
+                let mut contents = Vec::new();
+                write_fn(&state, &mut contents)?;
+                CompleteContents::Generated(contents)
+            }
+        };
+
+        let length = match &contents {
+            CompleteContents::Cached(file) => file.metadata()?.len(),
+            CompleteContents::Generated(contents) => contents.len() as u64,
+        };
+
+        Ok(CompleteOutput { length, contents })
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepted(accept_encoding: &str) -> Vec<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_str(accept_encoding).unwrap(),
+        );
+        accepted_encodings(&headers)
+    }
+
+    #[test]
+    fn test_accepted_encodings() {
+        assert_eq!(
+            accepted("gzip, deflate, br, zstd"),
+            [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip]
+        );
+        assert_eq!(
+            accepted("gzip;q=1.0, br;q=0.5"),
+            [Encoding::Gzip, Encoding::Brotli]
+        );
+        assert_eq!(accepted("*, br;q=0"), [Encoding::Zstd, Encoding::Gzip]);
+        assert_eq!(accepted("identity"), []);
+    }
+}
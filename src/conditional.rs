@@ -0,0 +1,380 @@
+use std::{
+    convert::Infallible,
+    io,
+    ops::RangeInclusive,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    http::{
+        header::{
+            ACCEPT_RANGES, CONTENT_ENCODING, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE,
+            LAST_MODIFIED, RANGE,
+        },
+        HeaderMap, HeaderValue,
+    },
+    response::{IntoResponseParts, Response, ResponseParts},
+};
+use sha2::{Digest, Sha256};
+
+// What a client can check its copy against. Sent as ETag and Last-Modified.
+#[derive(Clone, Debug)]
+pub struct Validators {
+    etag: String,
+    last_modified: Option<SystemTime>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Evaluation {
+    NotModified,
+    Full,
+    Partial(ByteRange),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    // bytes=500-
+    From(u64),
+    // bytes=500-999
+    Between(u64, u64),
+    // bytes=-500
+    Last(u64),
+}
+
+impl Validators {
+    // Sanitized output only changes when the source file or the sanitization policy does.
+    // The variant is anything else that changes the output, like which pretend file it is.
+    pub fn for_source(policy_version: &str, source_path: &Path, variant: &str) -> io::Result<Self> {
+        let metadata = std::fs::metadata(source_path)?;
+        let modified = metadata.modified()?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(policy_version);
+        hasher.update([0]);
+        hasher.update(source_path.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update(
+            modified
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+                .to_le_bytes(),
+        );
+        hasher.update(metadata.len().to_le_bytes());
+        hasher.update(variant);
+
+        Ok(Self {
+            etag: etag_from_hash(hasher),
+            last_modified: Some(modified),
+        })
+    }
+
+    // For responses that aren't backed by one file, like directory listings
+    pub fn for_contents(contents: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(contents);
+
+        Self {
+            etag: etag_from_hash(hasher),
+            last_modified: None,
+        }
+    }
+
+    pub fn evaluate(&self, request_headers: &HeaderMap) -> Evaluation {
+        // If-Modified-Since is only a fallback for clients that don't have an ETag
+        if let Some(if_none_match) = request_headers.get(IF_NONE_MATCH) {
+            if self.matches_any(if_none_match) {
+                return Evaluation::NotModified;
+            }
+        } else if let (Some(last_modified), Some(since)) = (
+            self.last_modified,
+            request_headers
+                .get(IF_MODIFIED_SINCE)
+                .and_then(|since| since.to_str().ok())
+                .and_then(parse_date),
+        ) {
+            if truncate_to_seconds(last_modified) <= since {
+                return Evaluation::NotModified;
+            }
+        }
+
+        let Some(range) = request_headers
+            .get(RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(parse_range)
+        else {
+            return Evaluation::Full;
+        };
+
+        // A client resuming a download of something that changed needs the whole thing again
+        if let Some(if_range) = request_headers.get(IF_RANGE) {
+            if !self.if_range_matches(if_range) {
+                return Evaluation::Full;
+            }
+        }
+
+        Evaluation::Partial(range)
+    }
+
+    // Weak comparison, as If-None-Match uses. Encoded copies are the same contents.
+    fn matches_any(&self, if_none_match: &HeaderValue) -> bool {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+
+        if_none_match.trim() == "*"
+            || if_none_match
+                .split(',')
+                .map(|etag| etag.trim())
+                .any(|etag| self.same_contents(etag.strip_prefix("W/").unwrap_or(etag)))
+    }
+
+    fn same_contents(&self, etag: &str) -> bool {
+        etag == self.etag
+            || etag
+                .strip_prefix(self.etag.trim_end_matches('"'))
+                .and_then(|suffix| suffix.strip_prefix('-'))
+                .is_some_and(|suffix| suffix.ends_with('"'))
+    }
+
+    // Strong comparison, and dates have to match exactly
+    fn if_range_matches(&self, if_range: &HeaderValue) -> bool {
+        let Ok(if_range) = if_range.to_str() else {
+            return false;
+        };
+
+        let if_range = if_range.trim();
+        if if_range.starts_with('"') || if_range.starts_with("W/") {
+            return if_range == self.etag;
+        }
+
+        match (self.last_modified, parse_date(if_range)) {
+            (Some(last_modified), Some(date)) => truncate_to_seconds(last_modified) == date,
+            _ => false,
+        }
+    }
+}
+
+impl IntoResponseParts for Validators {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            res.headers_mut().insert(ETAG, etag);
+        }
+
+        if let Some(last_modified) = self.last_modified {
+            if let Ok(last_modified) =
+                HeaderValue::from_str(&httpdate::fmt_http_date(last_modified))
+            {
+                res.headers_mut().insert(LAST_MODIFIED, last_modified);
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+impl ByteRange {
+    // None if none of it is in the output
+    pub fn resolve(self, length: u64) -> Option<RangeInclusive<u64>> {
+        let last_byte = length.checked_sub(1)?;
+
+        match self {
+            ByteRange::From(start) if start <= last_byte => Some(start..=last_byte),
+            ByteRange::Between(start, end) if start <= last_byte => {
+                Some(start..=end.min(last_byte))
+            }
+            ByteRange::Last(suffix_length) if suffix_length > 0 => {
+                Some(length.saturating_sub(suffix_length)..=last_byte)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Compressed bodies are different bytes, so they can't have the identity body's ETag.
+// Runs outside the compression layer, so it sees what the cache had compressed and what was compressed on the fly.
+// Ranges are only ever served from the identity bytes.
+pub async fn tag_encoded_responses(mut response: Response) -> Response {
+    let headers = response.headers();
+    let Some(encoding) = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|encoding| encoding.to_str().ok())
+    else {
+        return response;
+    };
+
+    let etag = headers
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .and_then(|etag| encoded_etag(etag, encoding));
+
+    let headers = response.headers_mut();
+    if let Some(etag) = etag {
+        headers.insert(ETAG, etag);
+    }
+    headers.remove(ACCEPT_RANGES);
+
+    response
+}
+
+// "abc" sent as br becomes "abc-br", so resuming it can't mix in bytes of another encoding
+fn encoded_etag(etag: &str, encoding: &str) -> Option<HeaderValue> {
+    let etag = etag.strip_suffix('"')?;
+    HeaderValue::from_str(&format!("{etag}-{encoding}\"")).ok()
+}
+
+fn etag_from_hash(hasher: Sha256) -> String {
+    format!("\"{:.32x}\"", hasher.finalize())
+}
+
+// Only single ranges are supported. Anything else gets the whole file, which is allowed.
+fn parse_range(range: &str) -> Option<ByteRange> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    match (start.is_empty(), end.is_empty()) {
+        (false, true) => Some(ByteRange::From(start.parse().ok()?)),
+
+        (false, false) => {
+            let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+            (start <= end).then_some(ByteRange::Between(start, end))
+        }
+
+        (true, false) => Some(ByteRange::Last(end.parse().ok()?)),
+
+        (true, true) => None,
+    }
+}
+
+fn parse_date(date: &str) -> Option<SystemTime> {
+    httpdate::parse_http_date(date).ok()
+}
+
+// HTTP dates don't have subsecond precision
+fn truncate_to_seconds(time: SystemTime) -> SystemTime {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(validators: &Validators, request_headers: &[(&'static str, &str)]) -> Evaluation {
+        let mut headers = HeaderMap::new();
+        for (name, value) in request_headers {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+
+        validators.evaluate(&headers)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let last_modified = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let validators = Validators {
+            etag: "\"abc\"".to_owned(),
+            last_modified: Some(last_modified),
+        };
+        let date = httpdate::fmt_http_date(last_modified);
+        let earlier = httpdate::fmt_http_date(last_modified - Duration::from_secs(60));
+
+        assert_eq!(evaluate(&validators, &[]), Evaluation::Full);
+        assert_eq!(
+            evaluate(&validators, &[("if-none-match", "\"xyz\", W/\"abc\"")]),
+            Evaluation::NotModified
+        );
+        assert_eq!(
+            evaluate(&validators, &[("if-none-match", "\"xyz\"")]),
+            Evaluation::Full
+        );
+        assert_eq!(
+            evaluate(&validators, &[("if-modified-since", &date)]),
+            Evaluation::NotModified
+        );
+        assert_eq!(
+            evaluate(&validators, &[("if-modified-since", &earlier)]),
+            Evaluation::Full
+        );
+
+        // If-None-Match wins over If-Modified-Since
+        assert_eq!(
+            evaluate(
+                &validators,
+                &[("if-none-match", "\"xyz\""), ("if-modified-since", &date)]
+            ),
+            Evaluation::Full
+        );
+
+        assert_eq!(
+            evaluate(&validators, &[("range", "bytes=100-")]),
+            Evaluation::Partial(ByteRange::From(100))
+        );
+        assert_eq!(
+            evaluate(
+                &validators,
+                &[("range", "bytes=100-"), ("if-range", "\"abc\"")]
+            ),
+            Evaluation::Partial(ByteRange::From(100))
+        );
+        assert_eq!(
+            evaluate(&validators, &[("range", "bytes=100-"), ("if-range", &date)]),
+            Evaluation::Partial(ByteRange::From(100))
+        );
+        assert_eq!(
+            evaluate(
+                &validators,
+                &[("range", "bytes=100-"), ("if-range", "W/\"abc\"")]
+            ),
+            Evaluation::Full
+        );
+        assert_eq!(
+            evaluate(
+                &validators,
+                &[("range", "bytes=100-"), ("if-range", &earlier)]
+            ),
+            Evaluation::Full
+        );
+
+        // The encoded copy is the same contents, but not the same bytes to take a range of
+        assert_eq!(
+            evaluate(&validators, &[("if-none-match", "\"abc-br\"")]),
+            Evaluation::NotModified
+        );
+        assert_eq!(
+            evaluate(
+                &validators,
+                &[("range", "bytes=100-"), ("if-range", "\"abc-br\"")]
+            ),
+            Evaluation::Full
+        );
+        assert_eq!(
+            encoded_etag("\"abc\"", "zstd").unwrap(),
+            HeaderValue::from_static("\"abc-zstd\"")
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(parse_range("bytes=0-99"), Some(ByteRange::Between(0, 99)));
+        assert_eq!(parse_range("bytes=-50"), Some(ByteRange::Last(50)));
+        assert_eq!(parse_range("bytes=0-1, 5-6"), None);
+        assert_eq!(parse_range("bytes=9-1"), None);
+        assert_eq!(parse_range("lines=0-1"), None);
+
+        assert_eq!(ByteRange::From(10).resolve(100), Some(10..=99));
+        assert_eq!(ByteRange::From(100).resolve(100), None);
+        assert_eq!(ByteRange::Between(90, 200).resolve(100), Some(90..=99));
+        assert_eq!(ByteRange::Last(200).resolve(100), Some(0..=99));
+        assert_eq!(ByteRange::Last(0).resolve(100), None);
+        assert_eq!(ByteRange::From(0).resolve(0), None);
+    }
+}
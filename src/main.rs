@@ -5,9 +5,14 @@ use eyre::Context;
 use tracing_subscriber::prelude::*;
 
 mod app_state;
+mod cache;
+mod conditional;
 mod ongoing_round_protection;
 mod parsers;
+mod rounds;
 mod route;
+mod search;
+mod streaming;
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
@@ -24,11 +29,26 @@ async fn main() -> eyre::Result<()> {
     );
     tracing::info!("hosting on {}", state.config.address);
 
+    if state.search.is_some() {
+        tokio::spawn(search::refresh_loop(Arc::clone(&state)));
+    }
+
     let listener = tokio::net::TcpListener::bind(state.config.address).await?;
     let app = Router::new()
         .route("/", axum::routing::get(route::get))
         .route("/{*path}", axum::routing::get(route::get))
+        .route("/runtime-diff", axum::routing::get(route::runtime_diff))
+        .route(
+            "/runtime-fingerprint",
+            axum::routing::get(route::runtime_fingerprint),
+        )
+        .route("/search", axum::routing::get(route::search))
         .route("/favicon.ico", axum::routing::get(|| async { "" }))
+        // Anything the cache already has compressed is left alone, since it has Content-Encoding
+        .layer(tower_http::compression::CompressionLayer::new())
+        .layer(axum::middleware::map_response(
+            conditional::tag_encoded_responses,
+        ))
         .with_state(state);
 
     axum::serve(listener, app.into_make_service()).await?;
@@ -113,6 +113,7 @@ impl Drop for OngoingRoundProtection {
     }
 }
 
+#[allow(clippy::map_flatten)]
 async fn fetch_ongoing_rounds(serverinfo_url: &str) -> eyre::Result<HashMap<String, u64>> {
     let server_info_bytes = reqwest::get(serverinfo_url)
         .await?
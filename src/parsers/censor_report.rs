@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use super::Strategy;
+
+// Counts of what sanitizing a file did. Only ever counts, so it can't leak anything censored.
+#[derive(Debug, serde::Serialize)]
+pub struct CensorReport {
+    strategy: Strategy,
+
+    lines: u64,
+    censored_lines: u64,
+
+    // Censor kind, like "private logtype" -> lines censored as it
+    censored_by_kind: BTreeMap<String, u64>,
+
+    // Log category -> lines in it, for the logs that have them
+    categories: BTreeMap<String, CategoryCounts>,
+
+    // Filter, like "ip" -> lines (or JSON values) it redacted something from
+    redactions: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+struct CategoryCounts {
+    lines: u64,
+    censored_lines: u64,
+}
+
+impl CensorReport {
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            lines: 0,
+            censored_lines: 0,
+            censored_by_kind: BTreeMap::new(),
+            categories: BTreeMap::new(),
+            redactions: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_line(&mut self, category: Option<&str>, censored: Option<&str>) {
+        self.lines += 1;
+
+        if let Some(censored) = censored {
+            self.censored_lines += 1;
+            increment(&mut self.censored_by_kind, censored_kind(censored));
+        }
+
+        if let Some(category) = category {
+            // Malformed lines could put anything where the category goes
+            let category = if category
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == '-')
+                && !category.is_empty()
+            {
+                category
+            } else {
+                "(unknown)"
+            };
+
+            let counts = match self.categories.get_mut(category) {
+                Some(counts) => counts,
+                None => self.categories.entry(category.to_owned()).or_default(),
+            };
+
+            counts.lines += 1;
+            if censored.is_some() {
+                counts.censored_lines += 1;
+            }
+        }
+    }
+
+    pub fn record_redaction(&mut self, filter: &'static str) {
+        *self.redactions.entry(filter).or_default() += 1;
+    }
+}
+
+fn increment(counts: &mut BTreeMap<String, u64>, key: &str) {
+    match counts.get_mut(key) {
+        Some(count) => *count += 1,
+        None => {
+            counts.insert(key.to_owned(), 1);
+        }
+    }
+}
+
+// "-censored(private logtype)-" -> "private logtype"
+// Also handles the runtime log's "-censored (string output)"
+fn censored_kind(censored: &str) -> &str {
+    match censored.strip_prefix("-censored") {
+        Some(kind) => kind
+            .trim_start()
+            .trim_end_matches('-')
+            .trim_start_matches('(')
+            .trim_end_matches(')'),
+        None => censored,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::{
+        game::{sanitize_game_log, GameLogFilter},
+        rules::Rules,
+    };
+
+    #[test]
+    fn test_censored_game_log_categories() {
+        let log = "\
+[2023-11-01 12:00:00.000] SAY: hello
+[2023-11-01 12:00:01.000] ADMINPRIVATE: secret
+[2023-11-01 12:00:02.000] ADMINPRIVATE: another secret
+not a line at all
+";
+
+        let mut report = CensorReport::new(Strategy::Game);
+        sanitize_game_log(
+            &Rules::default(),
+            &GameLogFilter::default(),
+            log.as_bytes(),
+            &mut std::io::sink(),
+            &mut report,
+        )
+        .unwrap();
+
+        let report = serde_json::to_value(&report).unwrap();
+        assert_eq!(report["lines"], 4);
+        assert_eq!(report["censored_lines"], 3);
+        assert_eq!(
+            report["categories"],
+            serde_json::json!({
+                "ADMINPRIVATE": { "lines": 2, "censored_lines": 2 },
+                "SAY": { "lines": 1, "censored_lines": 0 },
+            })
+        );
+        assert_eq!(report["censored_by_kind"]["private logtype"], 2);
+    }
+}
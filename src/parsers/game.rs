@@ -1,8 +1,17 @@
-use std::{borrow::Cow, sync::LazyLock};
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    sync::LazyLock,
+};
 
-use regex::{Regex, RegexSet};
+use regex::Regex;
 
-use super::ip_filtering::filter_ips;
+use super::{
+    censor_report::CensorReport,
+    filter_line, for_each_line,
+    rules::{CategoryAction, Rules},
+};
 
 // A macro to allow for &'static str returns
 macro_rules! censor {
@@ -11,20 +20,50 @@ macro_rules! censor {
     };
 }
 
+pub struct ParsedLine<'a> {
+    // Without the brackets, like "2023-11-01 12:00:00.123"
+    pub timestamp: Option<&'a str>,
+    // Without GAME-, GAME-COMPAT:, or the colon. Kept for censored lines, but only for counting.
+    pub category: Option<&'a str>,
+    // Everything after the category, as sanitized. None when censored.
+    pub message: Option<Cow<'a, str>>,
+    // The placeholder the line was replaced with, if it was censored
+    pub censored: Option<&'a str>,
+    // What goes into the sanitized game.log
+    pub text: Cow<'a, str>,
+}
+
+impl<'a> ParsedLine<'a> {
+    // The category, unless the line was censored
+    pub fn public_category(&self) -> Option<&'a str> {
+        self.category.filter(|_| self.censored.is_none())
+    }
+
+    fn censored(censored: &'a str) -> Self {
+        Self {
+            timestamp: None,
+            category: None,
+            message: None,
+            censored: Some(censored),
+            text: Cow::Borrowed(censored),
+        }
+    }
+}
+
 #[tracing::instrument(skip_all)]
-pub fn parse_line(line: &str) -> Cow<str> {
+pub fn parse_line<'a>(rules: &'a Rules, line: &'a str) -> ParsedLine<'a> {
     let line = line.trim();
 
     if line.is_empty() {
-        return censor!("empty_line").into();
+        return ParsedLine::censored(censor!("empty_line"));
     }
 
     if !line.starts_with('[') {
-        return censor!("no_ts_start").into();
+        return ParsedLine::censored(censor!("no_ts_start"));
     }
 
     let Some((timestamp, contents)) = line.split_once(']') else {
-        return censor!("no_category_colon").into(); // Matching PHP
+        return ParsedLine::censored(censor!("no_category_colon")); // Matching PHP
     };
 
     static TIMESTAMP_REGEX: LazyLock<Regex> = LazyLock::new(|| {
@@ -33,92 +72,546 @@ pub fn parse_line(line: &str) -> Cow<str> {
         ).unwrap()
     });
     if !TIMESTAMP_REGEX.is_match(&timestamp[1..]) {
-        return censor!("no_ts_regex_match").into();
+        return ParsedLine::censored(censor!("no_ts_regex_match"));
     }
 
     if contents.starts_with(" Starting up round ID ") {
-        return Cow::Borrowed(line);
+        return ParsedLine {
+            timestamp: Some(&timestamp[1..]),
+            category: None,
+            message: Some(Cow::Borrowed(&contents[1..])),
+            censored: None,
+            text: Cow::Borrowed(line),
+        };
     }
 
-    let mut words = contents.split(' ');
-    if words.next() != Some("") {
-        return censor!("no_space_after_timestamp").into();
+    let Some(contents) = contents.strip_prefix(' ') else {
+        return ParsedLine::censored(censor!("no_space_after_timestamp"));
+    };
+
+    let (next_word, mut message) = split_word(contents);
+    if !next_word.ends_with(':') {
+        return ParsedLine::censored(censor!("no_category_colon"));
     }
 
-    let log_type = {
-        let next_word = words.next().expect("out of words");
-        if !next_word.ends_with(':') {
-            return censor!("no_category_colon").into();
+    let log_type = if next_word == "GAME-COMPAT:" {
+        match message {
+            Some(rest) => {
+                let (next_word, rest) = split_word(rest);
+                message = rest;
+                next_word
+            }
+
+            None => return ParsedLine::censored(censor!("game_compat_no_followup")),
         }
+    } else {
+        next_word
+    };
 
-        if next_word == "GAME-COMPAT:" {
-            match words.next() {
-                Some(next_word) => next_word,
-                None => return censor!("game_compat_no_followup").into(),
+    let category = category_name(log_type);
+
+    let message = message.unwrap_or("");
+
+    match sanitize_message(rules, category, message) {
+        MessageAction::Keep => ParsedLine {
+            timestamp: Some(&timestamp[1..]),
+            category: Some(category),
+            message: Some(Cow::Borrowed(message)),
+            censored: None,
+            text: Cow::Borrowed(line),
+        },
+
+        MessageAction::Rewrite(message) => ParsedLine {
+            timestamp: Some(&timestamp[1..]),
+            category: Some(category),
+            text: Cow::Owned(format!("{timestamp}] {log_type} {message}")),
+            message: Some(Cow::Owned(message)),
+            censored: None,
+        },
+
+        // The text output doesn't say when it was, so neither can anything else.
+        // The category is only for counting in the censor report.
+        MessageAction::Censor(censored) => ParsedLine {
+            category: Some(category),
+            ..ParsedLine::censored(censored)
+        },
+    }
+}
+
+pub enum MessageAction<'a> {
+    Keep,
+    Rewrite(String),
+    Censor(&'a str),
+}
+
+// Applies the rule for the category to a message, which is everything after the category.
+// Shared with anything else that has game.log style categories, like the JSON logs.
+pub fn sanitize_message<'a>(rules: &'a Rules, category: &str, message: &str) -> MessageAction<'a> {
+    match rules.category_action(category) {
+        CategoryAction::Pass => MessageAction::Keep,
+
+        CategoryAction::Censor { censored } => MessageAction::Censor(censored),
+
+        CategoryAction::RegexCensor { censored, patterns } => {
+            if patterns.is_match(message) {
+                return MessageAction::Censor(censored);
             }
-        } else {
-            next_word
+
+            MessageAction::Keep
         }
-    };
 
-    match log_type[0..(log_type.len() - 1)].trim_start_matches("GAME-") {
-        "ACCESS" => match words.next() {
-            Some("Login:") => {
-                let mut words_vec = words.collect::<Vec<_>>();
+        CategoryAction::Access => {
+            let mut words = message.split(' ');
+
+            match words.next() {
+                Some("Login:") => {
+                    let mut words_vec = words.collect::<Vec<_>>();
 
-                let ip_cid_index = words_vec.len() - 4;
-                words_vec[ip_cid_index] = censor!("ip/cid");
+                    let Some(ip_cid_index) = words_vec.len().checked_sub(4) else {
+                        return MessageAction::Censor(censor!("ip/cid"));
+                    };
+                    words_vec[ip_cid_index] = censor!("ip/cid");
 
-                Cow::Owned(format!(
-                    "{timestamp}] {log_type} Login: {}",
-                    words_vec.join(" ")
-                ))
+                    MessageAction::Rewrite(format!("Login: {}", words_vec.join(" ")))
+                }
+
+                Some("Failed") => MessageAction::Censor(censor!("invalid connection data")),
+
+                _ => MessageAction::Keep,
             }
+        }
+    }
+}
 
-            Some("Failed") => censor!("invalid connection data").into(),
+// "GAME-SAY:" -> "SAY"
+pub fn category_name(log_type: &str) -> &str {
+    let mut chars = log_type.chars();
+    chars.next_back();
+    chars.as_str().trim_start_matches("GAME-")
+}
 
-            _ => Cow::Borrowed(line),
-        },
+// Like one step of split(' '), but keeps the rest as one string
+fn split_word(contents: &str) -> (&str, Option<&str>) {
+    match contents.split_once(' ') {
+        Some((word, rest)) => (word, Some(rest)),
+        None => (contents, None),
+    }
+}
+
+// Only looks at lines after they've been sanitized, so nothing censored can be filtered back out
+#[derive(Clone, Debug, Default)]
+pub struct GameLogFilter {
+    // Without GAME- and the colon, like "SAY"
+    pub categories: Vec<String>,
+    pub exclude: Vec<String>,
+
+    // "12:30:00" compares against the time, "2023-11-01 12:30:00" against the whole timestamp.
+    // Both ends are inclusive, so ?to=12:45 includes everything up to 12:46.
+    pub from: Option<String>,
+    pub to: Option<String>,
+
+    pub contains: Option<String>,
+}
+
+impl GameLogFilter {
+    pub fn is_empty(&self) -> bool {
+        self.categories.is_empty()
+            && self.exclude.is_empty()
+            && self.from.is_none()
+            && self.to.is_none()
+            && self.contains.is_none()
+    }
+
+    pub fn matches(&self, parsed_line: &ParsedLine) -> bool {
+        let category_matches = |categories: &[String]| {
+            parsed_line.public_category().is_some_and(|category| {
+                categories
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(category))
+            })
+        };
+
+        if !self.categories.is_empty() && !category_matches(&self.categories) {
+            return false;
+        }
+
+        if category_matches(&self.exclude) {
+            return false;
+        }
+
+        // When censored lines happened is censored too, or bisecting the window would find it
+        if self.from.is_some() || self.to.is_some() {
+            let Some(timestamp) = parsed_line
+                .timestamp
+                .filter(|_| parsed_line.censored.is_none())
+            else {
+                return false;
+            };
+
+            let comparable = |bound: &str| {
+                let timestamp = if bound.contains(' ') {
+                    timestamp
+                } else {
+                    timestamp.rsplit(' ').next().unwrap_or(timestamp)
+                };
+
+                &timestamp[..bound.len().min(timestamp.len())]
+            };
 
-        "ADMIN" => {
-            let remaining = words.collect::<Vec<_>>().join(" ");
-
-            static REGEX_SET: LazyLock<RegexSet> = LazyLock::new(|| {
-                RegexSet::new([
-                    r"^HELP:",
-                    r"^PM:",
-                    r"^ASAY:",
-                    r"^<a",
-                    r"^.*/\(.*\) : ",
-                    r"^.*/\(.*\) added note ",
-                    r"^.*/\(.*\) removed a note ",
-                    r"^.*/\(.*\) has added ",
-                    r"^.*/\(.*\) has edited ",
-                    r#"^[^:]*/\(.*\) ".*""#,
-                ])
-                .unwrap()
-            });
-
-            if REGEX_SET.is_match(&remaining) {
-                return censor!("asay/apm/ahelp/notes/etc").into();
+            if let Some(from) = &self.from {
+                if comparable(from) < from.as_str() {
+                    return false;
+                }
             }
 
-            Cow::Borrowed(line)
+            if let Some(to) = &self.to {
+                if comparable(to) > to.as_str() {
+                    return false;
+                }
+            }
+        }
+
+        self.contains
+            .as_ref()
+            .is_none_or(|contains| parsed_line.text.contains(contains.as_str()))
+    }
+}
+
+pub fn sanitize_game_log(
+    rules: &Rules,
+    filter: &GameLogFilter,
+    reader: impl BufRead,
+    writer: &mut dyn Write,
+    report: &mut CensorReport,
+) -> std::io::Result<()> {
+    for_each_line(reader, |line| {
+        let line = filter_line(line, report);
+        let parsed_line = parse_line(rules, &line);
+        report.record_line(parsed_line.category, parsed_line.censored);
+
+        if !filter.matches(&parsed_line) {
+            return Ok(());
+        }
+
+        writer.write_all(parsed_line.text.as_bytes())?;
+        writer.write_all(b"\n")
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ParsedGameLogFormat {
+    // One array of every line
+    Json,
+    // One object per line
+    Ndjson,
+}
+
+#[derive(serde::Serialize)]
+struct GameLogEntry<'a> {
+    timestamp: Option<&'a str>,
+    category: Option<&'a str>,
+    message: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    censored_reason: Option<&'a str>,
+}
+
+// Every line of the game.log, parsed after IPs and identifiers are filtered out
+pub fn for_each_parsed_line(
+    rules: &Rules,
+    reader: impl BufRead,
+    mut line_fn: impl FnMut(&ParsedLine) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut report = CensorReport::new(super::Strategy::Game);
+
+    for_each_line(reader, |line| {
+        let line = filter_line(line, &mut report);
+        line_fn(&parse_line(rules, &line))
+    })
+}
+
+// The sanitized game.log, but already split up, so nobody has to parse our text output
+pub fn write_parsed_game_log(
+    rules: &Rules,
+    reader: impl BufRead,
+    writer: &mut dyn Write,
+    format: ParsedGameLogFormat,
+    filter: &GameLogFilter,
+) -> std::io::Result<()> {
+    let mut first_line = true;
+
+    if let ParsedGameLogFormat::Json = format {
+        writer.write_all(b"[")?;
+    }
+
+    for_each_parsed_line(rules, reader, |parsed_line| {
+        if !filter.matches(parsed_line) {
+            return Ok(());
         }
 
-        "ADMINPRIVATE" => censor!("private logtype").into(),
+        let entry = GameLogEntry {
+            timestamp: parsed_line.timestamp,
+            category: parsed_line.public_category(),
+            message: parsed_line.message.as_deref(),
+            censored_reason: parsed_line.censored,
+        };
 
-        "TOPIC" => censor!("world_topic logs").into(),
+        match format {
+            ParsedGameLogFormat::Json => {
+                if !std::mem::take(&mut first_line) {
+                    writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut *writer, &entry)?;
+            }
 
-        "SQL" => censor!("sql logs").into(),
+            ParsedGameLogFormat::Ndjson => {
+                serde_json::to_writer(&mut *writer, &entry)?;
+                writer.write_all(b"\n")?;
+            }
+        }
 
-        _ => Cow::Borrowed(line),
+        Ok(())
+    })?;
+
+    if let ParsedGameLogFormat::Json = format {
+        writer.write_all(b"]")?;
     }
+
+    Ok(())
 }
 
-pub fn process_game_log(contents: String) -> String {
-    filter_ips(&contents)
-        .lines()
-        .map(parse_line)
-        .fold(String::new(), |a, b| a + &b + "\n")
+// Every category with at least one line that isn't censored, so private log types don't show up.
+// Anything that wouldn't work as a file name is skipped.
+pub fn categories(rules: &Rules, reader: impl BufRead) -> std::io::Result<BTreeSet<String>> {
+    let mut categories = BTreeSet::new();
+
+    for_each_parsed_line(rules, reader, |parsed_line| {
+        if let Some(category) = parsed_line.category {
+            if parsed_line.censored.is_none()
+                && !categories.contains(category)
+                && category
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                categories.insert(category.to_owned());
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(categories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules() {
+        let rules = Rules::default();
+
+        for (line, expected) in [
+            ("", "-censored(empty_line)-"),
+            ("hello", "-censored(no_ts_start)-"),
+            ("[12:00:00] SAY: hello", "[12:00:00] SAY: hello"),
+            (
+                "[2023-11-01 12:00:00.123] GAME-SAY: hello",
+                "[2023-11-01 12:00:00.123] GAME-SAY: hello",
+            ),
+            (
+                "[12:00:00] GAME-COMPAT: EMOTE: waves",
+                "[12:00:00] GAME-COMPAT: EMOTE: waves",
+            ),
+            (
+                "[12:00:00] Starting up round ID 1234.",
+                "[12:00:00] Starting up round ID 1234.",
+            ),
+            ("[12:00:00] nocolon here", "-censored(no_category_colon)-"),
+            (
+                "[12:00:00] ACCESS: Login: ckey/(Name) from 1.2.3.4-1234567890 || BYOND v515",
+                "[12:00:00] ACCESS: Login: ckey/(Name) from -censored(ip/cid)- || BYOND v515",
+            ),
+            (
+                "[12:00:00] ACCESS: Failed Login: ckey 1.2.3.4",
+                "-censored(invalid connection data)-",
+            ),
+            (
+                "[12:00:00] ADMIN: HELP: ckey/(Name): help me",
+                "-censored(asay/apm/ahelp/notes/etc)-",
+            ),
+            (
+                "[12:00:00] ADMIN: ckey/(Name) spawned a thing",
+                "[12:00:00] ADMIN: ckey/(Name) spawned a thing",
+            ),
+            (
+                "[12:00:00] ADMINPRIVATE: secret",
+                "-censored(private logtype)-",
+            ),
+            ("[12:00:00] TOPIC: secret", "-censored(world_topic logs)-"),
+            ("[12:00:00] SQL: secret", "-censored(sql logs)-"),
+        ] {
+            assert_eq!(parse_line(&rules, line).text, expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_parsed_game_log() {
+        let rules = Rules::default();
+        let log = "\
+[2023-11-01 12:00:00.123] Starting up round ID 1234.
+[2023-11-01 12:00:01.000] GAME-COMPAT: SAY: hello
+[2023-11-01 12:00:02.000] ACCESS: Login: ckey/(Name) from 1.2.3.4-1234567890 || BYOND v515
+[2023-11-01 12:00:03.000] ADMINPRIVATE: secret
+";
+
+        let mut json = Vec::new();
+        write_parsed_game_log(
+            &rules,
+            log.as_bytes(),
+            &mut json,
+            ParsedGameLogFormat::Json,
+            &GameLogFilter::default(),
+        )
+        .unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "timestamp": "2023-11-01 12:00:00.123",
+                    "category": null,
+                    "message": "Starting up round ID 1234.",
+                },
+                {
+                    "timestamp": "2023-11-01 12:00:01.000",
+                    "category": "SAY",
+                    "message": "hello",
+                },
+                {
+                    "timestamp": "2023-11-01 12:00:02.000",
+                    "category": "ACCESS",
+                    "message": "Login: ckey/(Name) from -censored(ip/cid)- || BYOND v515",
+                },
+                {
+                    "timestamp": null,
+                    "category": null,
+                    "message": null,
+                    "censored_reason": "-censored(private logtype)-",
+                },
+            ])
+        );
+
+        let mut ndjson = Vec::new();
+        write_parsed_game_log(
+            &rules,
+            log.as_bytes(),
+            &mut ndjson,
+            ParsedGameLogFormat::Ndjson,
+            &GameLogFilter::default(),
+        )
+        .unwrap();
+
+        let ndjson = String::from_utf8(ndjson).unwrap();
+        assert_eq!(ndjson.lines().count(), 4);
+        for (line, entry) in ndjson.lines().zip(json.as_array().unwrap()) {
+            assert_eq!(
+                &serde_json::from_str::<serde_json::Value>(line).unwrap(),
+                entry
+            );
+        }
+    }
+
+    #[test]
+    fn test_game_log_filter() {
+        let rules = Rules::default();
+        let log = "\
+[2023-11-01 12:29:59.999] SAY: too early
+[2023-11-01 12:30:00.000] GAME-SAY: hello
+[2023-11-01 12:31:00.000] EMOTE: waves
+[2023-11-01 12:32:00.000] ADMINPRIVATE: hello secret
+[2023-11-01 12:33:00.000] ACCESS: Login: ckey/(Name) from 1.2.3.4-1234567890 || BYOND v515
+[2023-11-01 12:45:30.000] SAY: still 12:45
+[2023-11-01 12:46:00.000] SAY: too late
+";
+
+        let filtered = |filter: GameLogFilter| {
+            let mut output = Vec::new();
+            sanitize_game_log(
+                &rules,
+                &filter,
+                log.as_bytes(),
+                &mut output,
+                &mut CensorReport::new(crate::parsers::Strategy::Game),
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            filtered(GameLogFilter {
+                categories: vec!["say".to_owned(), "EMOTE".to_owned()],
+                from: Some("12:30:00".to_owned()),
+                to: Some("12:45".to_owned()),
+                ..Default::default()
+            }),
+            "\
+[2023-11-01 12:30:00.000] GAME-SAY: hello
+[2023-11-01 12:31:00.000] EMOTE: waves
+[2023-11-01 12:45:30.000] SAY: still 12:45
+"
+        );
+
+        assert_eq!(
+            filtered(GameLogFilter {
+                exclude: vec!["SAY".to_owned(), "EMOTE".to_owned()],
+                from: Some("2023-11-01 12:32:00".to_owned()),
+                ..Default::default()
+            }),
+            "[2023-11-01 12:33:00.000] ACCESS: Login: ckey/(Name) from -censored(ip/cid)- || BYOND v515\n"
+        );
+
+        assert_eq!(
+            filtered(GameLogFilter {
+                from: Some("12:32:00".to_owned()),
+                to: Some("12:32:00".to_owned()),
+                ..Default::default()
+            }),
+            ""
+        );
+
+        // Censored text can't be searched for
+        assert_eq!(
+            filtered(GameLogFilter {
+                contains: Some("secret".to_owned()),
+                ..Default::default()
+            }),
+            ""
+        );
+        assert!(filtered(GameLogFilter {
+            contains: Some("1.2.3.4".to_owned()),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_categories() {
+        let rules = Rules::default();
+        let log = "\
+[2023-11-01 12:30:00.000] GAME-SAY: hello
+[2023-11-01 12:31:00.000] EMOTE: waves
+[2023-11-01 12:32:00.000] ADMINPRIVATE: hello secret
+[2023-11-01 12:33:00.000] SAY: again
+[2023-11-01 12:34:00.000] GAME-COMPAT: ATTACK: someone hit someone
+[2023-11-01 12:35:00.000] ../../etc: not a file name
+not even a line
+";
+
+        assert_eq!(
+            categories(&rules, log.as_bytes()).unwrap(),
+            BTreeSet::from(["ATTACK", "EMOTE", "SAY"].map(str::to_owned))
+        );
+    }
 }
@@ -0,0 +1,113 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, Write},
+    sync::LazyLock,
+};
+
+use ammonia::{Builder, UrlRelative};
+use regex::Regex;
+
+use super::{censor_report::CensorReport, filter_line};
+
+// Keeps the formatting the logs use, but nothing that runs or loads anything.
+// Scripts and styles are removed along with their contents, as are event handlers,
+// and any attribute with a URL in it, since the only URLs in these are BYOND links and external resources.
+static HTML_CLEANER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(["font"])
+        .add_tag_attributes("font", ["color", "size"])
+        .add_generic_attributes(["class"])
+        .url_schemes(HashSet::new())
+        .url_relative(UrlRelative::Deny);
+    builder
+});
+
+static TEXT_CLEANER: LazyLock<Builder<'static>> = LazyLock::new(Builder::empty);
+
+static LINE_BREAK_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<br\s*/?>").unwrap());
+
+// HTML can't be sanitized a line at a time, since elements can span lines.
+// These logs are small, so the whole file is read in.
+pub fn sanitize_html(
+    mut reader: impl BufRead,
+    writer: &mut dyn Write,
+    as_text: bool,
+    report: &mut CensorReport,
+) -> std::io::Result<()> {
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+
+    let contents: String = String::from_utf8_lossy(&contents)
+        .split_inclusive('\n')
+        .map(|line| {
+            report.record_line(None, None);
+            filter_line(line, report)
+        })
+        .collect();
+
+    if !as_text {
+        return HTML_CLEANER.clean(&contents).write_to(writer);
+    }
+
+    let text = TEXT_CLEANER
+        .clean(&LINE_BREAK_REGEX.replace_all(&contents, "\n"))
+        .to_string();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        writer.write_all(unescape_text(line).as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+// Text nodes are serialized with these escaped, and nothing else
+fn unescape_text(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::Strategy;
+
+    fn sanitize(contents: &str, as_text: bool) -> String {
+        let mut output = Vec::new();
+        sanitize_html(
+            contents.as_bytes(),
+            &mut output,
+            as_text,
+            &mut CensorReport::new(Strategy::Html),
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let contents = "<html><head><script>alert(1)</script><style>body { color: red; }</style></head>\n\
+            <body><font color='red' onclick='alert(1)'>12:00:00 Name died at 1.2.3.4 &amp; more</font><br>\n\
+            <img src='https://example.com/tracker.png'><a href='?src=123;ckey=foo'>JMP</a><br>\n\
+            </body></html>";
+
+        assert_eq!(
+            sanitize(contents, false),
+            "\n<font color=\"red\">12:00:00 Name died at -censored- &amp; more</font><br>\n\
+            <img><a rel=\"noopener noreferrer\">JMP</a><br>\n"
+        );
+
+        assert_eq!(
+            sanitize(contents, true),
+            "12:00:00 Name died at -censored- & more\nJMP\n"
+        );
+    }
+}
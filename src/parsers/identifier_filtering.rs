@@ -0,0 +1,60 @@
+use std::{borrow::Cow, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+// Computer IDs and BYOND account numbers, only when something says that's what they are,
+// e.g. "CID: 1234567890", "cid=1234567890", or "computer_id": "1234567890".
+static IDENTIFIER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)\b(cid|computer[_ ]?id|byond[_ ]?account(?:[_ ]?(?:number|id|no))?)("?[ \t]*[:=][ \t]*)("?)([0-9]+)"#,
+    )
+    .unwrap()
+});
+
+pub fn filter_identifiers(contents: &str) -> Cow<'_, str> {
+    IDENTIFIER_REGEX.replace_all(contents, |captures: &Captures| {
+        let key = &captures[1];
+        let separator = &captures[2];
+        let quote = &captures[3];
+
+        // An unquoted number after a quoted key is JSON, so keep it valid by making it a string
+        if quote.is_empty() && separator.starts_with('"') {
+            format!("{key}{separator}\"-censored-\"")
+        } else {
+            format!("{key}{separator}{quote}-censored-")
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_identifiers() {
+        for (input, expected) in [
+            (
+                "ckey/(Name) (IP: -censored-, CID: 1234567890)",
+                "ckey/(Name) (IP: -censored-, CID: -censored-)",
+            ),
+            ("cid=1234567890&ckey=foo", "cid=-censored-&ckey=foo"),
+            ("computer id: 1234567890", "computer id: -censored-"),
+            (
+                r#"{"ckey":"foo","computer_id":"1234567890"}"#,
+                r#"{"ckey":"foo","computer_id":"-censored-"}"#,
+            ),
+            (
+                r#"{"computer_id": 1234567890}"#,
+                r#"{"computer_id": "-censored-"}"#,
+            ),
+            (
+                "byond account number: 123456",
+                "byond account number: -censored-",
+            ),
+            ("acid: 10 units", "acid: 10 units"),
+            ("CID is unknown", "CID is unknown"),
+        ] {
+            assert_eq!(filter_identifiers(input), expected, "{input}");
+        }
+    }
+}
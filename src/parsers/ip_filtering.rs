@@ -1,11 +1,108 @@
-use std::{borrow::Cow, sync::LazyLock};
+use std::{borrow::Cow, net::Ipv6Addr, sync::LazyLock};
 
-use regex::Regex;
+use regex::{Captures, Regex};
+
+const CENSORED: &str = "-censored-";
 
 static IP_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"(?:(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9][0-9]|[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9][0-9]?|[0-9])").unwrap()
 });
 
-pub fn filter_ips(contents: &str) -> Cow<str> {
-    IP_REGEX.replace_all(contents, "-censored-")
+// Anything that could be an IPv6 address, including an embedded IPv4 address and a zone ID.
+// This also matches things like timestamps, so every match is checked with the real parser.
+static IPV6_CANDIDATE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"[0-9A-Fa-f]{0,4}(?::[0-9A-Fa-f]{0,4}){2,7}(?:\.[0-9]{1,3}){0,3}(?:%[0-9A-Za-z_.\-]+)?",
+    )
+    .unwrap()
+});
+
+pub fn filter_ips(contents: &str) -> Cow<'_, str> {
+    // IPv6 goes first so that mapped addresses like ::ffff:1.2.3.4 are censored as a whole
+    match filter_ipv6(contents) {
+        Cow::Borrowed(contents) => IP_REGEX.replace_all(contents, CENSORED),
+        Cow::Owned(contents) => Cow::Owned(IP_REGEX.replace_all(&contents, CENSORED).into_owned()),
+    }
+}
+
+fn filter_ipv6(contents: &str) -> Cow<'_, str> {
+    // Every address either compresses with :: or has at least six colons
+    if !contents.contains("::") && contents.matches(':').nth(5).is_none() {
+        return Cow::Borrowed(contents);
+    }
+
+    IPV6_CANDIDATE_REGEX.replace_all(contents, |captures: &Captures| {
+        let candidate = captures.get(0).unwrap();
+
+        // Don't censor the tail end of some larger word
+        let preceding = contents[..candidate.start()].chars().next_back();
+        let following = contents[candidate.end()..].chars().next();
+        if preceding.is_some_and(is_address_char) || following.is_some_and(is_address_char) {
+            return candidate.as_str().to_owned();
+        }
+
+        let address = match candidate.as_str().split_once('%') {
+            Some((address, _zone_id)) => address,
+            None => candidate.as_str(),
+        };
+
+        match address.parse::<Ipv6Addr>() {
+            // A bare "::" is much more likely to be punctuation than an address
+            Ok(address) if !address.is_unspecified() => CENSORED.to_owned(),
+            _ => candidate.as_str().to_owned(),
+        }
+    })
+}
+
+fn is_address_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || matches!(character, ':' | '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4() {
+        assert_eq!(filter_ips("from 1.2.3.4 ok"), "from -censored- ok");
+        assert_eq!(filter_ips("255.255.255.255"), "-censored-");
+    }
+
+    #[test]
+    fn test_ipv6() {
+        for (input, expected) in [
+            ("2001:0db8:85a3:0000:0000:8a2e:0370:7334", "-censored-"),
+            ("from 2001:db8::8a2e:370:7334 ok", "from -censored- ok"),
+            ("from ::1 ok", "from -censored- ok"),
+            ("fe80::", "-censored-"),
+            ("fe80::1%eth0 connected", "-censored- connected"),
+            ("fe80::1%25 connected", "-censored- connected"),
+            ("::ffff:1.2.3.4", "-censored-"),
+            ("(::ffff:192.168.0.1)", "(-censored-)"),
+            ("1:2:3:4:5:6:1.2.3.4", "-censored-"),
+            ("[2001:db8::1]:8080", "[-censored-]:8080"),
+            ("two 2001:db8::1 and ::2", "two -censored- and -censored-"),
+            ("ends with a period ::1.", "ends with a period -censored-."),
+            ("ADDR=2001:DB8::ABCD", "ADDR=-censored-"),
+        ] {
+            assert_eq!(filter_ips(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_not_ipv6() {
+        for input in [
+            "[12:30:00] SAY: hello",
+            "[2023-11-01 12:30:00.123] SAY: hello",
+            "mac 00:1a:2b:3c:4d:5e",
+            "a :: b",
+            "/datum/proc/foo::bar",
+            "beef:cafe",
+            "12:30:00:00",
+            "xyz::1",
+            "1:2:3:4:5:6:7:8:9",
+        ] {
+            assert_eq!(filter_ips(input), input);
+        }
+    }
 }
@@ -0,0 +1,150 @@
+use std::{
+    borrow::Cow,
+    io::{BufRead, Write},
+};
+
+use serde_json::Value;
+
+use super::{
+    censor_report::CensorReport,
+    filter_line, for_each_line,
+    game::{sanitize_message, MessageAction},
+    rules::Rules,
+};
+
+// The *.log.json files, which have one JSON object per line:
+// {"ts": "...", "category": "...", "message": "...", "data": {...}, ...}
+pub fn sanitize_json_log(
+    rules: &Rules,
+    reader: impl BufRead,
+    writer: &mut dyn Write,
+    report: &mut CensorReport,
+) -> std::io::Result<()> {
+    for_each_line(reader, |line| {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+
+        let entry = sanitize_entry(rules, line, report);
+        serde_json::to_writer(&mut *writer, &entry)?;
+        writer.write_all(b"\n")
+    })
+}
+
+fn sanitize_entry(rules: &Rules, line: &str, report: &mut CensorReport) -> Value {
+    const INVALID_JSON_CENSORED: &str = "-censored(invalid_json)-";
+
+    let Ok(Value::Object(mut entry)) = serde_json::from_str::<Value>(line) else {
+        report.record_line(None, Some(INVALID_JSON_CENSORED));
+
+        return serde_json::json!({
+            "message": INVALID_JSON_CENSORED,
+        });
+    };
+
+    let category = match entry.get("category") {
+        Some(Value::String(category)) => category.to_uppercase(),
+        _ => String::new(),
+    };
+
+    let message = match entry.get("message") {
+        Some(Value::String(message)) => message.as_str(),
+        _ => "",
+    };
+
+    let category = json_category_name(&category);
+
+    match sanitize_message(rules, category, message) {
+        MessageAction::Keep => report.record_line(Some(category), None),
+
+        MessageAction::Rewrite(message) => {
+            report.record_line(Some(category), None);
+            entry.insert("message".to_owned(), Value::String(message));
+        }
+
+        // Data is whatever went into the message, so it has to go too
+        MessageAction::Censor(censored) => {
+            report.record_line(Some(category), Some(censored));
+            entry.insert("message".to_owned(), Value::String(censored.to_owned()));
+            entry.remove("data");
+        }
+    }
+
+    let mut entry = Value::Object(entry);
+    filter_strings(&mut entry, report);
+    entry
+}
+
+// JSON categories don't have the colon that game.log categories do
+fn json_category_name(category: &str) -> &str {
+    category
+        .strip_suffix(':')
+        .unwrap_or(category)
+        .trim_start_matches("GAME-")
+}
+
+fn filter_strings(value: &mut Value, report: &mut CensorReport) {
+    match value {
+        Value::String(string) => {
+            if let Cow::Owned(filtered) = filter_line(string, report) {
+                *string = filtered;
+            }
+        }
+
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| filter_strings(value, report)),
+
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|value| filter_strings(value, report)),
+
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::Strategy;
+
+    fn sanitize(line: &str) -> String {
+        let mut output = Vec::new();
+        sanitize_json_log(
+            &Rules::default(),
+            line.as_bytes(),
+            &mut output,
+            &mut CensorReport::new(Strategy::JsonLog),
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_sanitize_json_log() {
+        assert_eq!(
+            sanitize(r#"{"category":"attack","message":"hit from 1.2.3.4","data":{"cid":"CID: 12345"}}"#),
+            "{\"category\":\"attack\",\"data\":{\"cid\":\"CID: -censored-\"},\"message\":\"hit from -censored-\"}\n"
+        );
+
+        assert_eq!(
+            sanitize(r#"{"category":"game-adminprivate","message":"secret","data":{"secret":1}}"#),
+            "{\"category\":\"game-adminprivate\",\"message\":\"-censored(private logtype)-\"}\n"
+        );
+
+        assert_eq!(
+            sanitize(r#"{"category":"ADMIN","message":"PM: ckey->ckey: hi"}"#),
+            "{\"category\":\"ADMIN\",\"message\":\"-censored(asay/apm/ahelp/notes/etc)-\"}\n"
+        );
+
+        assert_eq!(
+            sanitize(r#"{"category":"attack","message":"unterminated"#),
+            "{\"message\":\"-censored(invalid_json)-\"}\n"
+        );
+
+        assert_eq!(
+            sanitize("[1, 2, 3]"),
+            "{\"message\":\"-censored(invalid_json)-\"}\n"
+        );
+    }
+}
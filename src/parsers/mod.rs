@@ -1,120 +1,153 @@
-use std::{ffi::OsStr, path::Path};
+use std::{
+    borrow::Cow,
+    ffi::OsStr,
+    io::{self, BufRead, Write},
+    path::Path,
+};
 
-mod game;
+pub mod censor_report;
+pub mod game;
+mod html;
+mod identifier_filtering;
 mod ip_filtering;
+mod json_log;
+pub mod round_summary;
+pub mod rules;
 pub mod runtimes;
 
-// Given a path, returns a function that will take the contents of that file and return the sanitized version.
-pub fn get_file_sanitization_strategy(path: &Path) -> Option<fn(String) -> String> {
+use censor_report::CensorReport;
+use identifier_filtering::filter_identifiers;
+use ip_filtering::filter_ips;
+use rules::Rules;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    Game,
+    Runtimes,
+    JsonLog,
+    Html,
+    PassThrough,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SanitizeOptions {
+    // Serve HTML logs as their text, rather than as sanitized HTML
+    pub html_as_text: bool,
+
+    // Which lines of a game.log to keep, after they're sanitized
+    pub game_filter: game::GameLogFilter,
+}
+
+impl Strategy {
+    pub fn content_type(self, path: &Path, options: &SanitizeOptions) -> &'static str {
+        if self == Strategy::Html && !options.html_as_text {
+            "text/html"
+        } else if path.extension().and_then(OsStr::to_str) == Some("json") {
+            "application/json"
+        } else {
+            "text/plain"
+        }
+    }
+
+    // Reads the file line by line, writing the sanitized version as it goes.
+    pub fn sanitize(
+        self,
+        rules: &Rules,
+        options: &SanitizeOptions,
+        reader: impl BufRead,
+        writer: &mut dyn Write,
+        report: &mut CensorReport,
+    ) -> io::Result<()> {
+        match self {
+            Strategy::Game => {
+                game::sanitize_game_log(rules, &options.game_filter, reader, writer, report)
+            }
+            Strategy::Runtimes => runtimes::sanitize_runtimes_log(reader, writer, report),
+            Strategy::JsonLog => json_log::sanitize_json_log(rules, reader, writer, report),
+            Strategy::Html => html::sanitize_html(reader, writer, options.html_as_text, report),
+            Strategy::PassThrough => sanitize_pass_through(reader, writer, report),
+        }
+    }
+}
+
+// Given a path, returns the strategy that will take the contents of that file and return the sanitized version.
+pub fn get_file_sanitization_strategy(rules: &Rules, path: &Path) -> Option<Strategy> {
     let filename = path.file_name().and_then(OsStr::to_str)?;
+    rules.file_strategy(filename)
+}
+
+// Leaves everything the same, line endings included, other than identifiers
+fn sanitize_pass_through(
+    mut reader: impl BufRead,
+    writer: &mut dyn Write,
+    report: &mut CensorReport,
+) -> io::Result<()> {
+    let mut buffer = Vec::new();
+
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(());
+        }
 
-    match filename {
-        "game.log" => Some(game::process_game_log),
-
-        // Runtime condensing is done in the runtimes.rs parser
-        "runtime.log" => Some(runtimes::process_runtimes_log),
-
-        // Pass through, but replace .txt with .log
-        "asset.log"
-        | "asset.log.json"
-        | "atmos.html"
-        | "attack.log.json"
-        | "attack.log"
-        | "botany.html"
-        | "cargo.html"
-        | "circuit.html"
-        | "cloning.log.json"
-        | "cloning.log"
-        | "crafting.html"
-        | "deaths.html"
-        | "dynamic.json"
-        | "dynamic.log.json"
-        | "dynamic.log"
-        | "econ.log.json"
-        | "econ.log"
-        | "economy.log.json"
-        | "economy.log"
-        | "engine.html"
-        | "experimentor.html"
-        | "gravity.html"
-        | "hallucinations.html"
-        | "harddel.log.json"
-        | "harddel.log"
-        | "harddels.log.json"
-        | "harddels.log"
-        | "hypertorus.html"
-        | "id_card_changes.html"
-        | "init_profiler.json"
-        | "init_times.json"
-        | "initialize.log.json"
-        | "initialize.log"
-        | "job_debug.log.json"
-        | "job_debug.log"
-        | "kudzu.html"
-        | "manifest.log.json"
-        | "manifest.log"
-        | "map_errors.log.json"
-        | "map_errors.log"
-        | "mecha.log.json"
-        | "mecha.log"
-        | "mob_tags.log.json"
-        | "mob_tags.log"
-        | "nanites.html"
-        | "newscaster.json"
-        | "overlay.log.json"
-        | "overlay.log"
-        | "paper.log.json"
-        | "paper.log"
-        | "pda.log.json"
-        | "pda.log"
-        | "portals.html"
-        | "presents.html"
-        | "profiler.json"
-        | "qdel.log.json"
-        | "qdel.log"
-        | "radiation.html"
-        | "records.html"
-        | "research.html"
-        | "round_end_data.html"
-        | "round_end_data.json"
-        | "sendmaps.json"
-        | "shuttle.log.json"
-        | "shuttle.log"
-        | "signal.log.json"
-        | "signal.log"
-        | "signals.log.json"
-        | "signals.log"
-        | "silicon.log.json"
-        | "silicon.log"
-        | "silo.json"
-        | "silo.log.json"
-        | "silo.log"
-        | "singulo.html"
-        | "speech_indicators.log.json"
-        | "speech_indicators.log"
-        | "supermatter.html"
-        | "target_zone_switch.json"
-        | "telecomms.log.json"
-        | "telecomms.log"
-        | "telesci.html"
-        | "tool.log.json"
-        | "tool.log"
-        | "tools.log.json"
-        | "tools.log"
-        | "uplink.log.json"
-        | "uplink.log"
-        | "virus.log.json"
-        | "virus.log"
-        | "wires.html" => Some(std::convert::identity),
-
-        perf_filename if perf_filename.starts_with("perf-") => Some(std::convert::identity),
-
-        _ => None,
+        report.record_line(None, None);
+
+        match filter_identifiers(&String::from_utf8_lossy(&buffer)) {
+            Cow::Borrowed(line) => writer.write_all(line.as_bytes())?,
+            Cow::Owned(line) => {
+                report.record_redaction("identifier");
+                writer.write_all(line.as_bytes())?;
+            }
+        }
     }
 }
 
-// Separate so we can tracy it
-#[tracing::instrument(skip_all)]
-fn read_to_string(path: &Path) -> std::io::Result<String> {
-    std::fs::read_to_string(path)
+// Redacts IPs and identifiers, recording which of them did anything
+fn filter_line<'a>(line: &'a str, report: &mut CensorReport) -> Cow<'a, str> {
+    let line = filter_ips(line);
+    if let Cow::Owned(_) = line {
+        report.record_redaction("ip");
+    }
+
+    let filtered = match filter_identifiers(&line) {
+        Cow::Owned(filtered) => Some(filtered),
+        Cow::Borrowed(_) => None,
+    };
+
+    match filtered {
+        Some(filtered) => {
+            report.record_redaction("identifier");
+            Cow::Owned(filtered)
+        }
+
+        None => line,
+    }
+}
+
+// Calls the function with every line in the reader, without the line ending.
+// Invalid UTF-8 is replaced rather than failing the whole file.
+pub fn for_each_line(
+    mut reader: impl BufRead,
+    mut line_fn: impl FnMut(&str) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut buffer = Vec::new();
+
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(());
+        }
+
+        let mut line = &buffer[..];
+        if let Some(stripped) = line.strip_suffix(b"\n") {
+            line = stripped;
+
+            if let Some(stripped) = line.strip_suffix(b"\r") {
+                line = stripped;
+            }
+        }
+
+        line_fn(&String::from_utf8_lossy(line))?;
+    }
 }
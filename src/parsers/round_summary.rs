@@ -0,0 +1,444 @@
+use std::{io, path::Path, sync::LazyLock};
+
+use regex::Regex;
+
+use super::{
+    censor_report::CensorReport, game::for_each_parsed_line, get_file_sanitization_strategy,
+    rules::Rules, SanitizeOptions, Strategy,
+};
+
+// The files a round summary is built from
+pub const ROUND_SUMMARY_SOURCES: [&str; 3] = ["game.log", "dynamic.json", "deaths.html"];
+
+static RE_ROUND_ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^Starting up round ID (\d+)").unwrap());
+
+static RE_MAP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^Loading (?:(?i:map):? )?(.+?)\.*$").unwrap());
+
+// "recalled" doesn't match the first, since there's no word boundary before "called"
+static RE_SHUTTLE_CALL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bcalled the (?:emergency )?shuttle").unwrap());
+
+static RE_SHUTTLE_RECALL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\brecalled the (?:emergency )?shuttle").unwrap());
+
+// Every entry in deaths.html starts with when it happened, anything else is a header or a wrapped line
+static RE_DEATH_ENTRY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[?(?:\d{4}-\d{2}-\d{2} )?\d{2}:\d{2}:\d{2}").unwrap());
+
+static RE_VOTE_STARTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bstarted\b").unwrap());
+
+// "<b>Vote Result: Restart Round</b>", possibly after the tally, with the newlines escaped
+static RE_VOTE_RESULT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bvote result:\s*(.+?)\s*(?:<|\\n|$)").unwrap());
+
+// An overview of a round, from the sanitized versions of its logs.
+// Anything that couldn't be found is left out, rather than guessed.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RoundSummary {
+    round_id: Option<u64>,
+    map: Option<String>,
+
+    // As written in the game.log
+    started_at: Option<String>,
+    ended_at: Option<String>,
+    duration_seconds: Option<u64>,
+
+    shuttle_calls: Vec<TimelineEvent>,
+    shuttle_recalls: Vec<TimelineEvent>,
+    votes: Vec<Vote>,
+
+    // From dynamic.json
+    game_mode: Option<String>,
+    threat_level: Option<f64>,
+
+    // Entries in deaths.html
+    deaths: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct TimelineEvent {
+    timestamp: String,
+    message: String,
+}
+
+// Either half can be missing, if it wasn't logged or was censored
+#[derive(Debug, Default, serde::Serialize)]
+struct Vote {
+    started: Option<TimelineEvent>,
+    ended_at: Option<String>,
+
+    // What won, like "Restart Round" or "Inconclusive - No Votes!"
+    result: Option<String>,
+}
+
+pub fn summarize_round(rules: &Rules, round_path: &Path) -> io::Result<RoundSummary> {
+    let mut summary = RoundSummary::default();
+
+    let game_log = round_path.join("game.log");
+    if get_file_sanitization_strategy(rules, &game_log) == Some(Strategy::Game) {
+        match std::fs::File::open(&game_log) {
+            Ok(file) => for_each_parsed_line(rules, io::BufReader::new(file), |parsed_line| {
+                let (Some(timestamp), Some(message)) =
+                    (parsed_line.timestamp, &parsed_line.message)
+                else {
+                    return Ok(());
+                };
+
+                summary.add_game_line(timestamp, parsed_line.category, message);
+                Ok(())
+            })?,
+
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    if let Some(dynamic) = sanitized(rules, &round_path.join("dynamic.json"))? {
+        // Only written for dynamic rounds
+        let dynamic: serde_json::Value = serde_json::from_str(&dynamic).unwrap_or_default();
+
+        summary.game_mode = Some(
+            ["mode", "game_mode"]
+                .into_iter()
+                .find_map(|key| dynamic[key].as_str())
+                .unwrap_or("dynamic")
+                .to_owned(),
+        );
+        summary.threat_level = dynamic["threat_level"].as_f64();
+    }
+
+    if let Some(deaths) = sanitized(rules, &round_path.join("deaths.html"))? {
+        summary.deaths = Some(
+            deaths
+                .lines()
+                .filter(|line| RE_DEATH_ENTRY.is_match(line))
+                .count() as u64,
+        );
+    }
+
+    summary.duration_seconds = match (&summary.started_at, &summary.ended_at) {
+        (Some(started_at), Some(ended_at)) => {
+            match (
+                seconds_since_epoch(started_at),
+                seconds_since_epoch(ended_at),
+            ) {
+                // Rounds with only times in their timestamps can go past midnight
+                (Some(start), Some(end)) if end < start && !started_at.contains(' ') => {
+                    Some(end + 24 * 60 * 60 - start)
+                }
+                (Some(start), Some(end)) => end.checked_sub(start),
+                _ => None,
+            }
+        }
+
+        _ => None,
+    };
+
+    Ok(summary)
+}
+
+impl RoundSummary {
+    fn add_game_line(&mut self, timestamp: &str, category: Option<&str>, message: &str) {
+        if self.started_at.is_none() {
+            self.started_at = Some(timestamp.to_owned());
+        }
+        self.ended_at = Some(timestamp.to_owned());
+
+        let event = || TimelineEvent {
+            timestamp: timestamp.to_owned(),
+            message: message.to_owned(),
+        };
+
+        match category {
+            None => {
+                if let Some(round_id) = RE_ROUND_ID.captures(message) {
+                    self.round_id = round_id[1].parse().ok();
+                }
+            }
+
+            Some("GAME") => {
+                if self.map.is_none() {
+                    if let Some(map) = RE_MAP.captures(message) {
+                        self.map = Some(map[1].to_owned());
+                    }
+                }
+
+                if RE_SHUTTLE_RECALL.is_match(message) {
+                    self.shuttle_recalls.push(event());
+                } else if RE_SHUTTLE_CALL.is_match(message) {
+                    self.shuttle_calls.push(event());
+                }
+            }
+
+            Some("VOTE") => {
+                if let Some(result) = RE_VOTE_RESULT.captures(message) {
+                    let vote = match self.votes.last_mut() {
+                        Some(vote) if vote.result.is_none() => vote,
+                        _ => {
+                            self.votes.push(Vote::default());
+                            self.votes.last_mut().expect("just pushed")
+                        }
+                    };
+
+                    vote.ended_at = Some(timestamp.to_owned());
+                    vote.result = Some(result[1].to_owned());
+                } else if RE_VOTE_STARTED.is_match(message) {
+                    self.votes.push(Vote {
+                        started: Some(event()),
+                        ..Default::default()
+                    });
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        let text = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map_or_else(|| "unknown".to_owned(), ammonia::clean_text)
+        };
+
+        let number =
+            |value: Option<u64>| value.map_or_else(|| "unknown".to_owned(), |n| n.to_string());
+
+        let duration = self.duration_seconds.map_or_else(
+            || "unknown".to_owned(),
+            |seconds| {
+                format!(
+                    "{}h {:02}m {:02}s",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )
+            },
+        );
+
+        let rows = [
+            ("Round ID", number(self.round_id)),
+            ("Map", text(&self.map)),
+            ("Game mode", text(&self.game_mode)),
+            (
+                "Threat level",
+                self.threat_level
+                    .map_or_else(|| "unknown".to_owned(), |threat| threat.to_string()),
+            ),
+            ("Started", text(&self.started_at)),
+            ("Ended", text(&self.ended_at)),
+            ("Duration", duration),
+            ("Shuttle calls", self.shuttle_calls.len().to_string()),
+            ("Shuttle recalls", self.shuttle_recalls.len().to_string()),
+            ("Votes", self.votes.len().to_string()),
+            ("Deaths", number(self.deaths)),
+        ]
+        .into_iter()
+        .map(|(name, value)| format!("<tr><th>{name}</th><td>{value}</td></tr>"))
+        .collect::<String>();
+
+        // (timestamp, kind, message)
+        let mut events = [
+            ("Shuttle called", &self.shuttle_calls),
+            ("Shuttle recalled", &self.shuttle_recalls),
+        ]
+        .into_iter()
+        .flat_map(|(kind, events)| {
+            events
+                .iter()
+                .map(move |event| (&event.timestamp, kind, &event.message))
+        })
+        .collect::<Vec<_>>();
+
+        for vote in &self.votes {
+            if let Some(started) = &vote.started {
+                events.push((&started.timestamp, "Vote started", &started.message));
+            }
+
+            if let (Some(ended_at), Some(result)) = (&vote.ended_at, &vote.result) {
+                events.push((ended_at, "Vote result", result));
+            }
+        }
+
+        events.sort_by_key(|(timestamp, _, _)| *timestamp);
+
+        let timeline = events
+            .into_iter()
+            .map(|(timestamp, kind, message)| {
+                format!(
+                    "<tr><td>{}</td><td>{kind}</td><td>{}</td></tr>",
+                    ammonia::clean_text(timestamp),
+                    ammonia::clean_text(message)
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "<html>
+            <head>
+                <title>Round {round_id}</title>
+            </head>
+            <body>
+                <table>{rows}</table>
+                <hr />
+                <table>{timeline}</table>
+            </body>
+        </html>",
+            round_id = number(self.round_id),
+        )
+    }
+}
+
+// The file as it would be served, as text. None if it's missing or hidden.
+fn sanitized(rules: &Rules, path: &Path) -> io::Result<Option<String>> {
+    let Some(strategy) = get_file_sanitization_strategy(rules, path) else {
+        return Ok(None);
+    };
+
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    let mut output = Vec::new();
+    strategy.sanitize(
+        rules,
+        &SanitizeOptions {
+            html_as_text: true,
+            ..Default::default()
+        },
+        io::BufReader::new(file),
+        &mut output,
+        &mut CensorReport::new(strategy),
+    )?;
+
+    Ok(Some(String::from_utf8_lossy(&output).into_owned()))
+}
+
+// "2023-11-01 12:00:00.123" or "12:00:00", ignoring the fraction
+fn seconds_since_epoch(timestamp: &str) -> Option<u64> {
+    let (date, time) = match timestamp.split_once(' ') {
+        Some((date, time)) => (Some(date), time),
+        None => (None, timestamp),
+    };
+
+    let mut time = time.split('.').next()?.split(':');
+    let mut next = || time.next()?.parse::<u64>().ok();
+    let seconds = next()? * 3600 + next()? * 60 + next()?;
+
+    let Some(date) = date else {
+        return Some(seconds);
+    };
+
+    let mut date = date.split('-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    // Days from civil, from Howard Hinnant's date algorithms
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    u64::try_from(days)
+        .ok()
+        .map(|days| days * 24 * 60 * 60 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_round() {
+        let directory = std::env::temp_dir().join(format!("round-summary-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        std::fs::write(
+            directory.join("game.log"),
+            "\
+[2023-11-01 23:50:00.000] Starting up round ID 1234.
+[2023-11-01 23:50:01.000] GAME: Loading MetaStation...
+[2023-11-01 23:55:00.000] VOTE: ckey/(Name) started a vote for restart
+[2023-11-01 23:55:30.000] VOTE: ckey/(Name) voted for Continue Playing
+[2023-11-01 23:56:00.000] VOTE: <b>Restart Vote</b>\\n<b>Restart Round:</b> 1\\n<b>Vote Result: Continue Playing</b>
+[2023-11-02 00:10:00.000] VOTE: <b>Vote Result: Inconclusive - No Votes!</b>
+[2023-11-02 00:05:00.000] GAME: ckey/(Name) has called the shuttle.
+[2023-11-02 00:06:00.000] GAME: ckey/(Name) has recalled the shuttle.
+[2023-11-02 00:07:00.000] SAY: ckey/(Name) \"who recalled the shuttle? call it again\" (Bar (100,90,2))
+[2023-11-02 00:08:00.000] SAY: ckey/(Name) \"I called the shuttle, it's coming\" (Bar (100,90,2))
+[2023-11-02 00:20:00.000] ADMINPRIVATE: called the shuttle, secretly
+[2023-11-02 00:30:00.000] GAME: ckey/(Name) has called the emergency shuttle.
+[2023-11-02 01:00:30.500] GAME: round ended
+",
+        )
+        .unwrap();
+
+        std::fs::write(directory.join("dynamic.json"), r#"{"threat_level": 42.5}"#).unwrap();
+        std::fs::write(
+            directory.join("deaths.html"),
+            [
+                "<html><head><meta http-equiv='Content-Type' content='text/html; charset=UTF-8'></head><body>",
+                "<h1>Deaths</h1>",
+                "<small>2023-11-02 00:12:01 [0x2003f4a] (120,85,2)</small> || John Doe has died (BRUTE: 200, BURN: 0, TOX: 0, OXY: 0, STAM: 0) at Medbay (120, 85, 2).<br>",
+                "<small>2023-11-02 00:40:13 [0x2004b1c] (100,90,2)</small> || Jane Roe has died (BRUTE: 0, BURN: 150,",
+                "TOX: 10, OXY: 45, STAM: 0) at Bar (100, 90, 2).<br>",
+                "<script>alert(1)</script>",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let summary = summarize_round(&Rules::default(), &directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["round_id"], 1234);
+        assert_eq!(json["map"], "MetaStation");
+        assert_eq!(json["started_at"], "2023-11-01 23:50:00.000");
+        assert_eq!(json["ended_at"], "2023-11-02 01:00:30.500");
+        assert_eq!(json["duration_seconds"], 70 * 60 + 30);
+        assert_eq!(json["shuttle_calls"].as_array().unwrap().len(), 2);
+        assert_eq!(json["shuttle_recalls"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            json["votes"],
+            serde_json::json!([
+                {
+                    "started": {
+                        "timestamp": "2023-11-01 23:55:00.000",
+                        "message": "ckey/(Name) started a vote for restart",
+                    },
+                    "ended_at": "2023-11-01 23:56:00.000",
+                    "result": "Continue Playing",
+                },
+                {
+                    "started": null,
+                    "ended_at": "2023-11-02 00:10:00.000",
+                    "result": "Inconclusive - No Votes!",
+                },
+            ])
+        );
+        assert_eq!(json["game_mode"], "dynamic");
+        assert_eq!(json["threat_level"], 42.5);
+        assert_eq!(json["deaths"], 2);
+
+        let html = summary.to_html();
+        assert!(html.contains("<tr><th>Duration</th><td>1h 10m 30s</td></tr>"));
+        assert!(html.contains("<td>Vote result</td><td>Continue&#32;Playing</td>"));
+    }
+
+    #[test]
+    fn test_seconds_since_epoch() {
+        assert_eq!(seconds_since_epoch("1970-01-01 00:00:00.000"), Some(0));
+        assert_eq!(
+            seconds_since_epoch("2023-11-01 12:00:00.123"),
+            Some(1698840000)
+        );
+        assert_eq!(seconds_since_epoch("12:00:01"), Some(12 * 3600 + 1));
+        assert_eq!(seconds_since_epoch("garbage"), None);
+    }
+}
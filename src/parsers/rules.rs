@@ -0,0 +1,171 @@
+use std::{collections::HashMap, path::Path};
+
+use eyre::Context;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::RegexSet;
+use sha2::{Digest, Sha256};
+
+use super::Strategy;
+
+// Bump whenever a code change would change what gets sanitized, even with the same rules.
+const SANITIZATION_CODE_VERSION: u32 = 1;
+
+const DEFAULT_RULES: &str = include_str!("default_rules.toml");
+
+#[derive(Debug)]
+pub struct Rules {
+    file_globs: GlobSet,
+    // Index of a glob in file_globs -> strategy of the rule it came from
+    file_strategies: Vec<Strategy>,
+
+    categories: HashMap<String, CategoryAction>,
+
+    policy_version: String,
+}
+
+#[derive(Debug)]
+pub enum CategoryAction {
+    Pass,
+    Censor {
+        censored: String,
+    },
+    RegexCensor {
+        censored: String,
+        patterns: RegexSet,
+    },
+    Access,
+}
+
+#[derive(serde::Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    files: Vec<FileRuleFile>,
+
+    #[serde(default)]
+    categories: HashMap<String, CategoryActionFile>,
+}
+
+#[derive(serde::Deserialize)]
+struct FileRuleFile {
+    strategy: Strategy,
+    names: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum CategoryActionFile {
+    Pass,
+    Censor {
+        reason: String,
+    },
+    RegexCensor {
+        reason: String,
+        patterns: Vec<String>,
+    },
+    Access,
+}
+
+impl Rules {
+    // Loads the default rules, with the rules file at the given path on top if it exists.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                tracing::info!("loading sanitization rules from {}", path.display());
+                Self::from_sources(&[&contents, DEFAULT_RULES])
+                    .with_context(|| format!("loading {}", path.display()))
+            }
+
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                Self::from_sources(&[DEFAULT_RULES])
+            }
+
+            Err(error) => Err(error).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    // Earlier rules files take priority over later ones.
+    fn from_sources(sources: &[&str]) -> eyre::Result<Self> {
+        let mut file_globs = GlobSetBuilder::new();
+        let mut file_strategies = Vec::new();
+        let mut categories = HashMap::new();
+
+        let mut policy_hasher = Sha256::new();
+        policy_hasher.update(SANITIZATION_CODE_VERSION.to_le_bytes());
+
+        for source in sources {
+            policy_hasher.update(source.len().to_le_bytes());
+            policy_hasher.update(source);
+
+            let rules_file: RulesFile = toml::from_str(source).context("parsing rules")?;
+
+            for file_rule in rules_file.files {
+                for name in file_rule.names {
+                    file_globs.add(
+                        Glob::new(&name).with_context(|| format!("invalid file name {name}"))?,
+                    );
+                    file_strategies.push(file_rule.strategy);
+                }
+            }
+
+            for (category, action) in rules_file.categories {
+                if categories.contains_key(&category) {
+                    continue;
+                }
+
+                let action = match action {
+                    CategoryActionFile::Pass => CategoryAction::Pass,
+                    CategoryActionFile::Censor { reason } => CategoryAction::Censor {
+                        censored: censored(&reason),
+                    },
+                    CategoryActionFile::RegexCensor { reason, patterns } => {
+                        CategoryAction::RegexCensor {
+                            censored: censored(&reason),
+                            patterns: RegexSet::new(patterns)
+                                .with_context(|| format!("invalid patterns for {category}"))?,
+                        }
+                    }
+                    CategoryActionFile::Access => CategoryAction::Access,
+                };
+
+                categories.insert(category, action);
+            }
+        }
+
+        Ok(Self {
+            file_globs: file_globs.build().context("building file globs")?,
+            file_strategies,
+            categories,
+            policy_version: format!("{:x}", policy_hasher.finalize())[..16].to_owned(),
+        })
+    }
+
+    pub fn file_strategy(&self, filename: &str) -> Option<Strategy> {
+        self.file_globs
+            .matches(filename)
+            .into_iter()
+            .min()
+            .map(|index| self.file_strategies[index])
+    }
+
+    // Changes whenever the rules or the code that applies them do, so anything sanitized under
+    // an old policy can be thrown out.
+    pub fn policy_version(&self) -> &str {
+        &self.policy_version
+    }
+
+    pub fn category_action(&self, category: &str) -> &CategoryAction {
+        self.categories
+            .get(category)
+            .unwrap_or(&CategoryAction::Pass)
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::from_sources(&[DEFAULT_RULES]).expect("couldn't load default rules")
+    }
+}
+
+fn censored(reason: &str) -> String {
+    format!("-censored({reason})-")
+}
@@ -1,28 +1,141 @@
-use std::{borrow::Cow, collections::HashMap, iter::Peekable, sync::LazyLock};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{BufRead, Write},
+    sync::LazyLock,
+};
 
 use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use self::{
+    entry::{read_details, RuntimeDetails},
+    filter::RuntimeFilter,
+    normalize::normalize_message,
+};
+use crate::parsers::{
+    censor_report::CensorReport, filter_line, for_each_line,
+    identifier_filtering::filter_identifiers, ip_filtering::filter_ips,
+};
+
+pub mod aggregate;
+pub mod diff;
+mod entry;
+pub mod filter;
+mod normalize;
+pub mod tree;
+
+pub fn sanitize_runtimes_log(
+    reader: impl BufRead,
+    writer: &mut dyn Write,
+    report: &mut CensorReport,
+) -> std::io::Result<()> {
+    let mut first_line = true;
+
+    for_each_line(reader, |line| {
+        // Lines are joined rather than terminated, so there's no trailing newline
+        if !std::mem::take(&mut first_line) {
+            writer.write_all(b"\n")?;
+        }
 
-use crate::parsers::ip_filtering::filter_ips;
+        let line = filter_line(line, report);
+        let sanitized_line = sanitize_runtimes_line(&line);
+        report.record_line(
+            None,
+            matches!(sanitized_line, Cow::Owned(_)).then_some(STRING_OUTPUT_CENSORED),
+        );
 
-pub fn process_runtimes_log(contents: String) -> String {
-    contents
-        .lines()
-        .map(|line| sanitize_runtimes_line(line))
-        .collect::<Vec<_>>()
-        .join("\n")
+        writer.write_all(sanitized_line.as_bytes())
+    })
 }
 
+const STRING_OUTPUT_CENSORED: &str = "-censored (string output)";
+
 // Remove BYOND printed strings
-fn sanitize_runtimes_line(line: &str) -> Cow<str> {
+fn sanitize_runtimes_line(line: &str) -> Cow<'_, str> {
     static STRING_OUTPUT_REGEX: LazyLock<Regex> =
         LazyLock::new(|| Regex::new(r#"^.*Cannot read ".*$"#).unwrap());
 
-    STRING_OUTPUT_REGEX.replace(line, "-censored (string output)")
+    STRING_OUTPUT_REGEX.replace(line, STRING_OUTPUT_CENSORED)
+}
+
+// The same, for lines that are kept as slices of the log
+fn censor_string_output(line: &str) -> &str {
+    match sanitize_runtimes_line(line) {
+        Cow::Borrowed(line) => line,
+        Cow::Owned(_) => STRING_OUTPUT_CENSORED,
+    }
+}
+
+// Extras on top of what the C++ condenser outputs. The default here is the C++ output,
+// the routes turn some of them on unless asked not to.
+#[derive(Clone, Debug, Default)]
+pub struct CondenseOptions {
+    // Occurrences per minute for every runtime, and first/last seen in the text output
+    pub histogram: bool,
+
+    // Use message templates as keys, so runtimes that only differ by refs, numbers etc. are one runtime
+    pub normalize: bool,
+
+    // Distinct usr, src and src.loc values from every occurrence, not just the first
+    pub samples: bool,
+
+    // Only for the condensed outputs of a single round, not aggregates
+    pub filter: RuntimeFilter,
+}
+
+// How many different raw messages to keep for each normalized one
+const MAX_EXAMPLES: usize = 3;
+
+// How many distinct values of usr, src and src.loc to show for each runtime
+const MAX_SAMPLES: usize = 5;
+
+// A few distinct values of a field, and how many distinct values there were in total
+#[derive(Debug, Default)]
+struct Samples<'a> {
+    distinct: HashSet<&'a str>,
+    samples: Vec<&'a str>,
+}
+
+impl<'a> Samples<'a> {
+    fn add(&mut self, value: Option<&'a str>) {
+        if let Some(value) = value {
+            if self.distinct.insert(value) && self.samples.len() < MAX_SAMPLES {
+                self.samples.push(value);
+            }
+        }
+    }
+}
+
+impl serde::Serialize for Samples<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Samples", 2)?;
+        state.serialize_field("distinct", &self.distinct.len())?;
+        state.serialize_field("samples", &self.samples)?;
+        state.end()
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+struct FieldSamples<'a> {
+    usr: Samples<'a>,
+    src: Samples<'a>,
+    src_loc: Samples<'a>,
+}
+
+impl<'a> FieldSamples<'a> {
+    fn add(&mut self, usr: Option<&'a str>, src: Option<&'a str>, src_loc: Option<&'a str>) {
+        self.usr.add(usr);
+        self.src.add(src);
+        self.src_loc.add(src_loc);
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, serde::Serialize)]
 struct CondensedRuntimeKey<'a> {
-    message: &'a str,
+    message: Cow<'a, str>,
     proc_name: &'a str,
 }
 
@@ -34,6 +147,26 @@ struct CondensedRuntimeValue<'a> {
     src_loc: Option<&'a str>,
 
     count: u64,
+
+    fingerprint: String,
+
+    // As written in the log, like "2023-11-01 12:00:00.000"
+    first_seen: &'a str,
+    last_seen: &'a str,
+
+    // Minute, like "2023-11-01 12:00" -> occurrences in it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    histogram: Option<BTreeMap<&'a str, u64>>,
+
+    // Raw messages that normalized to this one, only when normalizing
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    samples: Option<FieldSamples<'a>>,
+
+    // The whole first occurrence, with every field and the call stack
+    details: RuntimeDetails<'a>,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -45,19 +178,27 @@ struct CondensedRuntime<'a> {
     value: CondensedRuntimeValue<'a>,
 }
 
-pub fn condense_runtimes_to_string(contents: &str) -> String {
+pub fn condense_runtimes_to_string(contents: &str, options: CondenseOptions) -> String {
     let contents = filter_ips(contents);
+    let contents = filter_identifiers(&contents);
 
-    let condensed_runtimes = get_condensed_runtimes(&contents);
+    let condensed_runtimes = get_filtered_runtimes(&contents, &options);
 
     let mut lines = vec![
-		"Note: The source file, src and usr are all from the FIRST of the identical runtimes. Everything else is cropped.".to_owned(),
-		"".to_owned(),
-		format!("Total unique runtimes: {}", condensed_runtimes.runtimes.len()),
-		format!("Total runtimes: {}", condensed_runtimes.total_count),
-		"".to_owned(),
-		"** Runtimes **".to_owned(),
-	];
+        if options.samples {
+            format!("Note: The source file, src and usr are all from the FIRST of the identical runtimes. Up to {MAX_SAMPLES} distinct values of usr, src and src.loc are listed after them.")
+        } else {
+            "Note: The source file, src and usr are all from the FIRST of the identical runtimes. Everything else is cropped.".to_owned()
+        },
+        "".to_owned(),
+        format!(
+            "Total unique runtimes: {}",
+            condensed_runtimes.runtimes.len()
+        ),
+        format!("Total runtimes: {}", condensed_runtimes.total_count),
+        "".to_owned(),
+        "** Runtimes **".to_owned(),
+    ];
 
     for runtime in condensed_runtimes.runtimes {
         lines.push("".to_owned());
@@ -68,6 +209,13 @@ pub fn condense_runtimes_to_string(contents: &str) -> String {
         ));
 
         lines.push(format!("runtime error: {}", runtime.key.message));
+
+        for example in &runtime.value.examples {
+            if *example != runtime.key.message {
+                lines.push(format!("  example: {example}"));
+            }
+        }
+
         lines.push(format!("proc name: {}", runtime.key.proc_name));
 
         if let Some(source_file) = runtime.value.source_file {
@@ -81,6 +229,37 @@ pub fn condense_runtimes_to_string(contents: &str) -> String {
             lines.push(format!("  src.loc: {src_loc}"));
         }
 
+        if let Some(samples) = &runtime.value.samples {
+            for (name, samples) in [
+                ("usr", &samples.usr),
+                ("src", &samples.src),
+                ("src.loc", &samples.src_loc),
+            ] {
+                if samples.distinct.is_empty() {
+                    continue;
+                }
+
+                lines.push(format!(
+                    "  distinct {name} ({}): {}",
+                    samples.distinct.len(),
+                    samples.samples.join(", ")
+                ));
+            }
+        }
+
+        if let Some(histogram) = &runtime.value.histogram {
+            lines.push(format!("  first seen: {}", runtime.value.first_seen));
+            lines.push(format!("  last seen: {}", runtime.value.last_seen));
+            lines.push(format!(
+                "  per minute: {}",
+                histogram
+                    .iter()
+                    .map(|(minute, count)| format!("{minute} x{count}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
         lines.push("".to_owned());
     }
 
@@ -90,28 +269,189 @@ pub fn condense_runtimes_to_string(contents: &str) -> String {
     lines.join("\n")
 }
 
-pub fn condense_runtimes_to_json(contents: &str) -> serde_json::Value {
-    serde_json::to_value(get_condensed_runtimes(&filter_ips(contents)))
+pub fn condense_runtimes_to_json(contents: &str, options: CondenseOptions) -> serde_json::Value {
+    let contents = filter_ips(contents);
+    let contents = filter_identifiers(&contents);
+
+    serde_json::to_value(get_filtered_runtimes(&contents, &options))
         .expect("couldn't serialize json")
 }
 
+// For pasting into GitHub issues. One row per runtime, with everything else folded away.
+pub fn condense_runtimes_to_markdown(contents: &str, options: CondenseOptions) -> String {
+    let contents = filter_ips(contents);
+    let contents = filter_identifiers(&contents);
+
+    let condensed_runtimes = get_filtered_runtimes(&contents, &options);
+
+    let mut lines = vec![
+        format!(
+            "**{}** unique runtimes, **{}** in total. Details are from the first of the identical runtimes.",
+            condensed_runtimes.runtimes.len(),
+            condensed_runtimes.total_count
+        ),
+        "".to_owned(),
+        "| Count | Runtime | Proc | Source file |".to_owned(),
+        "| ---: | --- | --- | --- |".to_owned(),
+    ];
+
+    for runtime in &condensed_runtimes.runtimes {
+        let mut details = Vec::new();
+
+        for example in &runtime.value.examples {
+            if *example != runtime.key.message {
+                details.push(format!("example: {example}"));
+            }
+        }
+
+        for line in &runtime.value.details.message_lines {
+            details.push(line.to_string());
+        }
+
+        details.push(format!("usr: {}", runtime.value.usr));
+        details.push(format!("src: {}", runtime.value.src));
+
+        if let Some(src_loc) = runtime.value.src_loc {
+            details.push(format!("src.loc: {src_loc}"));
+        }
+
+        if let Some(samples) = &runtime.value.samples {
+            for (name, samples) in [
+                ("usr", &samples.usr),
+                ("src", &samples.src),
+                ("src.loc", &samples.src_loc),
+            ] {
+                if !samples.distinct.is_empty() {
+                    details.push(format!(
+                        "distinct {name} ({}): {}",
+                        samples.distinct.len(),
+                        samples.samples.join(", ")
+                    ));
+                }
+            }
+        }
+
+        details.push(format!("first seen: {}", runtime.value.first_seen));
+        details.push(format!("last seen: {}", runtime.value.last_seen));
+
+        if !runtime.value.details.call_stack.is_empty() {
+            details.push("call stack:".to_owned());
+            for frame in &runtime.value.details.call_stack {
+                details.push(match frame.src {
+                    Some(src) => format!("- {src}: {}", frame.proc),
+                    None => format!("- {}", frame.proc),
+                });
+            }
+        }
+
+        lines.push(format!(
+            "| {} | <details><summary>{}</summary>{}</details> | {} | {} |",
+            runtime.value.count,
+            markdown_cell(&runtime.key.message),
+            details
+                .iter()
+                .map(|line| markdown_cell(line))
+                .collect::<Vec<_>>()
+                .join("<br>"),
+            markdown_cell(runtime.key.proc_name),
+            markdown_cell(runtime.value.source_file.unwrap_or("")),
+        ));
+    }
+
+    lines.push("".to_owned());
+
+    lines.join("\n")
+}
+
+// For spreadsheets. Only the first occurrence's fields, like the text output.
+pub fn condense_runtimes_to_csv(contents: &str, options: CondenseOptions) -> String {
+    let contents = filter_ips(contents);
+    let contents = filter_identifiers(&contents);
+
+    let condensed_runtimes = get_filtered_runtimes(&contents, &options);
+
+    let mut lines = vec![
+        "count,message,proc_name,source_file,usr,src,src_loc,first_seen,last_seen,fingerprint"
+            .to_owned(),
+    ];
+
+    for runtime in &condensed_runtimes.runtimes {
+        lines.push(
+            [
+                &runtime.value.count.to_string(),
+                &*runtime.key.message,
+                runtime.key.proc_name,
+                runtime.value.source_file.unwrap_or(""),
+                runtime.value.usr,
+                runtime.value.src,
+                runtime.value.src_loc.unwrap_or(""),
+                runtime.value.first_seen,
+                runtime.value.last_seen,
+                &runtime.value.fingerprint,
+            ]
+            .map(csv_field)
+            .join(","),
+        );
+    }
+
+    lines.push("".to_owned());
+
+    lines.join("\r\n")
+}
+
+// Table cells can't have pipes or newlines, and the details are HTML, so escape that too
+fn markdown_cell(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('|', "\\|")
+        .replace('\n', "<br>")
+}
+
+fn csv_field(field: &str) -> Cow<'_, str> {
+    // Spreadsheets would run these as formulas
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{field}"))
+    } else {
+        Cow::Borrowed(field)
+    };
+
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        field
+    }
+}
+
 #[derive(serde::Serialize)]
 struct CondensedRuntimes<'a> {
     total_count: u64,
     runtimes: Vec<CondensedRuntime<'a>>,
 }
 
-fn get_condensed_runtimes(runtime_contents: &str) -> CondensedRuntimes {
+static RE_RUNTIME_ERROR_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[(.+?)\] (?:RUNTIME: )?runtime error: (.*)$").unwrap());
+
+static RE_RUNTIME_PROC_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ \- (?:proc|verb) name: (.+)$").unwrap());
+
+fn get_filtered_runtimes<'a>(
+    contents: &'a str,
+    options: &CondenseOptions,
+) -> CondensedRuntimes<'a> {
+    let mut condensed_runtimes = get_condensed_runtimes(contents, options);
+    options.filter.apply(&mut condensed_runtimes.runtimes);
+    condensed_runtimes
+}
+
+fn get_condensed_runtimes<'a>(
+    runtime_contents: &'a str,
+    options: &CondenseOptions,
+) -> CondensedRuntimes<'a> {
     let mut lines = runtime_contents.lines().peekable();
     let mut condensed_runtimes: HashMap<CondensedRuntimeKey, CondensedRuntimeValue> =
         HashMap::new();
 
-    static RE_RUNTIME_ERROR_START: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"^\[.+?\] (?:RUNTIME: )?runtime error: (.*)$").unwrap());
-
-    static RE_RUNTIME_PROC_NAME: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"^ \- (?:proc|verb) name: (.+)$").unwrap());
-
     let mut runtime_count = 0;
 
     'main_loop: while let Some(start_line) = lines.next() {
@@ -119,15 +459,17 @@ fn get_condensed_runtimes(runtime_contents: &str) -> CondensedRuntimes {
             continue;
         };
 
-        let runtime = runtime_error_start.get(1).unwrap();
+        let timestamp = runtime_error_start.get(1).unwrap().as_str();
+        let runtime = runtime_error_start.get(2).unwrap();
         runtime_count += 1;
 
         // Some runtimes are multi-line
+        let mut message_lines = Vec::new();
         let proc_name = loop {
             match lines.next() {
                 Some(next_line) => match RE_RUNTIME_PROC_NAME.captures(next_line) {
                     Some(proc_name) => break proc_name,
-                    None => continue,
+                    None => message_lines.push(censor_string_output(next_line)),
                 },
 
                 None => {
@@ -137,29 +479,53 @@ fn get_condensed_runtimes(runtime_contents: &str) -> CondensedRuntimes {
         };
 
         let condensed_runtime_key = CondensedRuntimeKey {
-            message: runtime.as_str(),
+            message: if options.normalize {
+                normalize_message(runtime.as_str())
+            } else {
+                Cow::Borrowed(runtime.as_str())
+            },
             proc_name: proc_name.get(1).unwrap().as_str(),
         };
 
+        let details = read_details(&mut lines, message_lines);
+        let source_file = details.field("source file");
+        let usr = details.field("usr");
+        let src = usr.and(details.field("src"));
+        let src_loc = src.and(details.field("src.loc"));
+
         if let Some(condensed_runtime_value) = condensed_runtimes.get_mut(&condensed_runtime_key) {
             condensed_runtime_value.count += 1;
+            if options.normalize
+                && condensed_runtime_value.examples.len() < MAX_EXAMPLES
+                && !condensed_runtime_value.examples.contains(&runtime.as_str())
+            {
+                condensed_runtime_value.examples.push(runtime.as_str());
+            }
+            condensed_runtime_value.last_seen = timestamp;
+            if let Some(histogram) = &mut condensed_runtime_value.histogram {
+                *histogram.entry(minute(timestamp)).or_default() += 1;
+            }
+            if let Some(samples) = &mut condensed_runtime_value.samples {
+                samples.add(usr, src, src_loc);
+            }
             continue;
         }
 
-        let source_file = read_field(&mut lines, "source file");
-
-        let Some(usr) = read_field(&mut lines, "usr") else {
+        let Some(usr) = usr else {
             tracing::error!("next line was not usr");
             continue;
         };
 
-        let Some(src) = read_field(&mut lines, "src") else {
+        let Some(src) = src else {
             tracing::error!("next line was not src");
             continue;
         };
 
-        // It's okay to not have this despite moving the line, since we're not going to get a relevant one anyway
-        let src_loc = read_field(&mut lines, "src.loc");
+        let fingerprint = runtime_fingerprint(
+            runtime.as_str(),
+            condensed_runtime_key.proc_name,
+            source_file,
+        );
 
         condensed_runtimes.insert(
             condensed_runtime_key,
@@ -169,6 +535,23 @@ fn get_condensed_runtimes(runtime_contents: &str) -> CondensedRuntimes {
                 src,
                 src_loc,
                 count: 1,
+                fingerprint,
+                first_seen: timestamp,
+                last_seen: timestamp,
+                histogram: options
+                    .histogram
+                    .then(|| BTreeMap::from([(minute(timestamp), 1)])),
+                examples: if options.normalize {
+                    vec![runtime.as_str()]
+                } else {
+                    Vec::new()
+                },
+                samples: options.samples.then(|| {
+                    let mut samples = FieldSamples::default();
+                    samples.add(Some(usr), Some(src), src_loc);
+                    samples
+                }),
+                details,
             },
         );
     }
@@ -177,7 +560,14 @@ fn get_condensed_runtimes(runtime_contents: &str) -> CondensedRuntimes {
         .into_iter()
         .map(|(key, value)| CondensedRuntime { key, value })
         .collect();
-    condensed_runtimes_sorted.sort_by_key(|runtime| u64::MAX - runtime.value.count);
+    // Ties are broken so the same log always condenses to the same bytes, which the ETag promises
+    condensed_runtimes_sorted.sort_by(|a, b| {
+        b.value
+            .count
+            .cmp(&a.value.count)
+            .then(a.key.message.cmp(&b.key.message))
+            .then(a.key.proc_name.cmp(b.key.proc_name))
+    });
 
     CondensedRuntimes {
         total_count: runtime_count,
@@ -185,24 +575,55 @@ fn get_condensed_runtimes(runtime_contents: &str) -> CondensedRuntimes {
     }
 }
 
-fn read_field<'a>(
-    peekable_lines: &mut Peekable<impl Iterator<Item = &'a str>>,
-    expecting: &'static str,
-) -> Option<&'a str> {
-    static RE_RUNTIME_FIELD: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"^ \-   (.+?): (.+)$").unwrap());
+// Stays the same across rounds, deploys, and code moving around in the file, as long as it's the same bug.
+// Always uses the normalized message, whether or not the runtimes were condensed with it.
+pub fn runtime_fingerprint(message: &str, proc_name: &str, source_file: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(normalize_message(message).as_bytes());
+    hasher.update([0]);
+    hasher.update(proc_name);
+    hasher.update([0]);
+    hasher.update(source_file.map(without_line_number).unwrap_or_default());
+
+    format!("{:.16x}", hasher.finalize())
+}
 
-    let line = peekable_lines.peek()?;
+pub fn is_fingerprint(fingerprint: &str) -> bool {
+    fingerprint.len() == 16 && fingerprint.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// How many times the runtime with that fingerprint happened
+pub fn count_fingerprint(contents: &str, fingerprint: &str) -> u64 {
+    let contents = filter_ips(contents);
+    let contents = filter_identifiers(&contents);
+
+    get_condensed_runtimes(
+        &contents,
+        &CondenseOptions {
+            normalize: true,
+            ..Default::default()
+        },
+    )
+    .runtimes
+    .iter()
+    .filter(|runtime| runtime.value.fingerprint.eq_ignore_ascii_case(fingerprint))
+    .map(|runtime| runtime.value.count)
+    .sum()
+}
 
-    let capture = RE_RUNTIME_FIELD.captures(line)?;
-    let field_captured = capture.get(1).unwrap();
-    if field_captured.as_str() != expecting {
-        return None;
+// "code/modules/mob/mob.dm,123" -> "code/modules/mob/mob.dm"
+fn without_line_number(source_file: &str) -> &str {
+    match source_file.rsplit_once(',') {
+        Some((file, line)) if line.chars().all(|c| c.is_ascii_digit()) => file,
+        _ => source_file,
     }
+}
 
-    peekable_lines.next();
-    let value = capture.get(2).unwrap();
-    Some(value.as_str())
+// "2023-11-01 12:00:00.000" -> "2023-11-01 12:00"
+fn minute(timestamp: &str) -> &str {
+    timestamp
+        .rsplit_once(':')
+        .map_or(timestamp, |(minute, _)| minute)
 }
 
 #[cfg(test)]
@@ -269,7 +690,8 @@ mod tests {
                 )
                 .unwrap();
 
-                let condensed_runtimes = condense_runtimes_to_string(&raw_runtimes);
+                let condensed_runtimes =
+                    condense_runtimes_to_string(&raw_runtimes, CondenseOptions::default());
 
                 // The C++ runtime condenser only sorts by count, which means everything else is unspecified.
                 let mut rust_split = condensed_runtimes
@@ -307,6 +729,258 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_timestamps() {
+        let runtimes = "\
+[2023-11-01 12:00:00.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+[2023-11-01 12:00:30.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+[2023-11-01 12:05:10.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+";
+
+        let condensed = condense_runtimes_to_json(runtimes, CondenseOptions::default());
+        let runtime = &condensed["runtimes"][0];
+        assert_eq!(runtime["first_seen"], "2023-11-01 12:00:00.000");
+        assert_eq!(runtime["last_seen"], "2023-11-01 12:05:10.000");
+        assert!(runtime.get("histogram").is_none());
+
+        let condensed = condense_runtimes_to_json(
+            runtimes,
+            CondenseOptions {
+                histogram: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            condensed["runtimes"][0]["histogram"],
+            serde_json::json!({ "2023-11-01 12:00": 2, "2023-11-01 12:05": 1 })
+        );
+    }
+
+    #[test]
+    fn test_tie_order() {
+        let runtimes = "\
+[2023-11-01 12:00:00.000] runtime error: b
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+[2023-11-01 12:00:01.000] runtime error: a
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+[2023-11-01 12:00:02.000] runtime error: a
+ - proc name: bar (/datum/proc/bar)
+ -   usr: null
+ -   src: null
+[2023-11-01 12:00:03.000] runtime error: c
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+[2023-11-01 12:00:04.000] runtime error: c
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+";
+
+        let condensed = condense_runtimes_to_json(runtimes, CondenseOptions::default());
+        let order: Vec<(&str, &str)> = condensed["runtimes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|runtime| {
+                (
+                    runtime["message"].as_str().unwrap(),
+                    runtime["proc_name"].as_str().unwrap(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            order,
+            [
+                ("c", "foo (/datum/proc/foo)"),
+                ("a", "bar (/datum/proc/bar)"),
+                ("a", "foo (/datum/proc/foo)"),
+                ("b", "foo (/datum/proc/foo)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let runtimes = "\
+[2023-11-01 12:00:00.000] runtime error: Cannot execute null.Destroy() on [0x2001234]
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+[2023-11-01 12:00:30.000] runtime error: Cannot execute null.Destroy() on [0x2001235]
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+";
+
+        let condensed = condense_runtimes_to_json(runtimes, CondenseOptions::default());
+        assert_eq!(condensed["runtimes"].as_array().unwrap().len(), 2);
+
+        let condensed = condense_runtimes_to_json(
+            runtimes,
+            CondenseOptions {
+                normalize: true,
+                ..Default::default()
+            },
+        );
+        let runtime = &condensed["runtimes"][0];
+        assert_eq!(
+            runtime["message"],
+            "Cannot execute null.Destroy() on [<ref>]"
+        );
+        assert_eq!(runtime["count"], 2);
+        assert_eq!(
+            runtime["examples"],
+            serde_json::json!([
+                "Cannot execute null.Destroy() on [0x2001234]",
+                "Cannot execute null.Destroy() on [0x2001235]",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_string_output_in_details() {
+        let runtimes = "\
+[2023-11-01 12:00:00.000] runtime error: list index out of bounds
+Cannot read \"secret\".len
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: null
+ -   src.loc: Cannot read \"also secret\".loc
+";
+
+        let condensed = condense_runtimes_to_json(runtimes, CondenseOptions::default());
+        let details = &condensed["runtimes"][0]["details"];
+        assert_eq!(
+            details["message_lines"],
+            serde_json::json!(["-censored (string output)"])
+        );
+        assert!(!condensed.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn test_samples() {
+        let runtimes = "\
+[2023-11-01 12:00:00.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: /obj/a (/obj/a)
+ -   src.loc: the floor (10,20,2) (/turf/open/floor)
+[2023-11-01 12:00:30.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: /obj/b (/obj/b)
+[2023-11-01 12:00:31.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   usr: null
+ -   src: /obj/a (/obj/a)
+";
+
+        let options = CondenseOptions {
+            samples: true,
+            ..Default::default()
+        };
+
+        let condensed = condense_runtimes_to_json(runtimes, options.clone());
+        assert_eq!(
+            condensed["runtimes"][0]["samples"],
+            serde_json::json!({
+                "usr": { "distinct": 1, "samples": ["null"] },
+                "src": { "distinct": 2, "samples": ["/obj/a (/obj/a)", "/obj/b (/obj/b)"] },
+                "src_loc": { "distinct": 1, "samples": ["the floor (10,20,2) (/turf/open/floor)"] },
+            })
+        );
+
+        assert!(condense_runtimes_to_string(runtimes, options)
+            .contains("  distinct src (2): /obj/a (/obj/a), /obj/b (/obj/b)\n"));
+    }
+
+    #[test]
+    fn test_markdown_and_csv() {
+        let runtimes = "\
+[2023-11-01 12:00:00.000] runtime error: bad index | 5, \"x\"
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: <null>
+[2023-11-01 12:00:01.000] runtime error: bad index | 5, \"x\"
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: <null>
+[2023-11-01 12:00:02.000] runtime error: =SUM(1,2)
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,13
+ -   usr: null
+ -   src: <null>
+";
+
+        let markdown = condense_runtimes_to_markdown(runtimes, CondenseOptions::default());
+        assert!(markdown.contains(
+            "| 2 | <details><summary>bad index \\| 5, \"x\"</summary>usr: null<br>src: &lt;null&gt;<br>"
+        ));
+        assert!(markdown.contains("</details> | foo (/datum/proc/foo) | code/foo.dm,12 |\n"));
+
+        let csv = condense_runtimes_to_csv(runtimes, CondenseOptions::default());
+        let mut rows = csv.lines();
+        assert!(rows.next().unwrap().starts_with("count,message,proc_name,"));
+        assert!(rows.next().unwrap().starts_with(
+            "2,\"bad index | 5, \"\"x\"\"\",foo (/datum/proc/foo),\"code/foo.dm,12\",null,<null>,,"
+        ));
+        assert!(rows
+            .next()
+            .unwrap()
+            .starts_with("1,\"'=SUM(1,2)\",foo (/datum/proc/foo),"));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let runtimes = "\
+[2023-11-01 12:00:00.000] runtime error: Cannot execute null.Destroy() on [0x2001234]
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: null
+[2023-11-01 12:00:30.000] runtime error: Cannot execute null.Destroy() on [0x2001235]
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: null
+";
+
+        let fingerprint = runtime_fingerprint(
+            "Cannot execute null.Destroy() on [0x1]",
+            "foo (/datum/proc/foo)",
+            Some("code/foo.dm,40"),
+        );
+        assert!(is_fingerprint(&fingerprint));
+
+        // Same whether or not it's normalized
+        for normalize in [false, true] {
+            let condensed = condense_runtimes_to_json(
+                runtimes,
+                CondenseOptions {
+                    normalize,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(condensed["runtimes"][0]["fingerprint"], *fingerprint);
+        }
+
+        assert_eq!(count_fingerprint(runtimes, &fingerprint), 2);
+        assert_eq!(count_fingerprint(runtimes, "0000000000000000"), 0);
+    }
+
     #[test]
     fn test_2023_11_logs() {
         test_log_directory(
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use super::{get_condensed_runtimes, CondenseOptions, MAX_EXAMPLES};
+use crate::parsers::{identifier_filtering::filter_identifiers, ip_filtering::filter_ips};
+
+// Condensed runtimes merged across many rounds
+pub struct RuntimeAggregate {
+    options: CondenseOptions,
+
+    pub(super) rounds: u64,
+    pub(super) total_count: u64,
+    pub(super) runtimes: HashMap<(String, String), AggregatedRuntime>,
+}
+
+#[derive(serde::Serialize)]
+pub(super) struct AggregatedRuntime {
+    pub(super) message: String,
+    pub(super) proc_name: String,
+
+    // From the first round it appeared in
+    pub(super) source_file: Option<String>,
+
+    fingerprint: String,
+
+    pub(super) count: u64,
+    rounds: u64,
+    first_round_id: u64,
+    last_round_id: u64,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct AggregatedRuntimes<'a> {
+    rounds: u64,
+    total_count: u64,
+    runtimes: Vec<&'a AggregatedRuntime>,
+}
+
+impl RuntimeAggregate {
+    pub fn new(options: CondenseOptions) -> Self {
+        Self {
+            options,
+            rounds: 0,
+            total_count: 0,
+            runtimes: HashMap::new(),
+        }
+    }
+
+    // Rounds are expected in order of round ID
+    pub fn add_round(&mut self, round_id: u64, contents: &str) {
+        let contents = filter_ips(contents);
+        let contents = filter_identifiers(&contents);
+
+        let condensed_runtimes = get_condensed_runtimes(&contents, &self.options);
+
+        self.rounds += 1;
+        self.total_count += condensed_runtimes.total_count;
+
+        for runtime in condensed_runtimes.runtimes {
+            let key = (
+                runtime.key.message.into_owned(),
+                runtime.key.proc_name.to_owned(),
+            );
+
+            match self.runtimes.get_mut(&key) {
+                Some(aggregated) => {
+                    aggregated.count += runtime.value.count;
+                    aggregated.rounds += 1;
+                    aggregated.first_round_id = aggregated.first_round_id.min(round_id);
+                    aggregated.last_round_id = aggregated.last_round_id.max(round_id);
+
+                    for example in runtime.value.examples {
+                        if aggregated.examples.len() >= MAX_EXAMPLES {
+                            break;
+                        }
+
+                        if !aggregated
+                            .examples
+                            .iter()
+                            .any(|existing| existing == example)
+                        {
+                            aggregated.examples.push(example.to_owned());
+                        }
+                    }
+                }
+
+                None => {
+                    self.runtimes.insert(
+                        key.clone(),
+                        AggregatedRuntime {
+                            message: key.0,
+                            proc_name: key.1,
+                            source_file: runtime.value.source_file.map(str::to_owned),
+                            fingerprint: runtime.value.fingerprint,
+                            count: runtime.value.count,
+                            rounds: 1,
+                            first_round_id: round_id,
+                            last_round_id: round_id,
+                            examples: runtime
+                                .value
+                                .examples
+                                .into_iter()
+                                .map(str::to_owned)
+                                .collect(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut runtimes: Vec<&AggregatedRuntime> = self.runtimes.values().collect();
+        runtimes.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(b.rounds.cmp(&a.rounds))
+                .then(a.message.cmp(&b.message))
+                .then(a.proc_name.cmp(&b.proc_name))
+        });
+
+        serde_json::to_value(AggregatedRuntimes {
+            rounds: self.rounds,
+            total_count: self.total_count,
+            runtimes,
+        })
+        .expect("couldn't serialize json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST_ROUND: &str = "\
+[2023-11-01 12:00:00.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: /datum (/datum)
+[2023-11-01 12:00:01.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: /datum (/datum)
+";
+
+    const SECOND_ROUND: &str = "\
+[2023-11-01 14:00:00.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: /datum (/datum)
+[2023-11-01 14:00:01.000] runtime error: division by zero
+ - proc name: bar (/datum/proc/bar)
+ -   source file: code/bar.dm,5
+ -   usr: null
+ -   src: null
+";
+
+    #[test]
+    fn test_aggregate() {
+        let mut aggregate = RuntimeAggregate::new(CondenseOptions::default());
+        aggregate.add_round(100, FIRST_ROUND);
+        aggregate.add_round(102, SECOND_ROUND);
+
+        let json = aggregate.to_json();
+        assert_eq!(json["rounds"], 2);
+        assert_eq!(json["total_count"], 4);
+
+        let foo = &json["runtimes"][0];
+        assert_eq!(foo["message"], "Cannot read null.x");
+        assert_eq!(foo["count"], 3);
+        assert_eq!(foo["rounds"], 2);
+        assert_eq!(foo["first_round_id"], 100);
+        assert_eq!(foo["last_round_id"], 102);
+
+        let bar = &json["runtimes"][1];
+        assert_eq!(bar["count"], 1);
+        assert_eq!(bar["first_round_id"], 102);
+        assert_eq!(bar["last_round_id"], 102);
+    }
+}
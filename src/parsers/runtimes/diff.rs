@@ -0,0 +1,240 @@
+use super::aggregate::RuntimeAggregate;
+
+// How different two sets of rounds' runtimes are, like before and after a deploy.
+// Counts are compared per round, so folders with different numbers of rounds can be compared.
+#[derive(serde::Serialize)]
+pub struct RuntimeDiff {
+    before: Side,
+    after: Side,
+
+    introduced: Vec<DiffedRuntime>,
+    disappeared: Vec<DiffedRuntime>,
+    changed: Vec<DiffedRuntime>,
+}
+
+#[derive(serde::Serialize)]
+struct Side {
+    rounds: u64,
+    total_count: u64,
+}
+
+#[derive(serde::Serialize)]
+struct DiffedRuntime {
+    message: String,
+    proc_name: String,
+    source_file: Option<String>,
+
+    before_count: u64,
+    after_count: u64,
+    before_per_round: f64,
+    after_per_round: f64,
+}
+
+// What counts as changing a lot
+#[derive(Clone, Copy, Debug)]
+pub struct DiffThresholds {
+    // How many times more (or less) often it has to happen per round
+    pub ratio: f64,
+
+    // So going from 1 to 2 doesn't count
+    pub min_difference: f64,
+}
+
+impl Default for DiffThresholds {
+    fn default() -> Self {
+        Self {
+            ratio: 2.0,
+            min_difference: 5.0,
+        }
+    }
+}
+
+impl DiffedRuntime {
+    fn change(&self) -> f64 {
+        (self.after_per_round - self.before_per_round).abs()
+    }
+}
+
+pub fn diff_runtimes(
+    before: &RuntimeAggregate,
+    after: &RuntimeAggregate,
+    thresholds: DiffThresholds,
+) -> RuntimeDiff {
+    let per_round =
+        |count: u64, aggregate: &RuntimeAggregate| count as f64 / aggregate.rounds.max(1) as f64;
+
+    let mut introduced = Vec::new();
+    let mut disappeared = Vec::new();
+    let mut changed = Vec::new();
+
+    for (key, before_runtime) in &before.runtimes {
+        let after_count = after.runtimes.get(key).map_or(0, |runtime| runtime.count);
+
+        let diffed = DiffedRuntime {
+            message: before_runtime.message.clone(),
+            proc_name: before_runtime.proc_name.clone(),
+            source_file: before_runtime.source_file.clone(),
+
+            before_count: before_runtime.count,
+            after_count,
+            before_per_round: per_round(before_runtime.count, before),
+            after_per_round: per_round(after_count, after),
+        };
+
+        if after_count == 0 {
+            disappeared.push(diffed);
+            continue;
+        }
+
+        let (lower, higher) = if diffed.before_per_round < diffed.after_per_round {
+            (diffed.before_per_round, diffed.after_per_round)
+        } else {
+            (diffed.after_per_round, diffed.before_per_round)
+        };
+
+        if higher >= lower * thresholds.ratio && diffed.change() >= thresholds.min_difference {
+            changed.push(diffed);
+        }
+    }
+
+    for (key, after_runtime) in &after.runtimes {
+        if before.runtimes.contains_key(key) {
+            continue;
+        }
+
+        introduced.push(DiffedRuntime {
+            message: after_runtime.message.clone(),
+            proc_name: after_runtime.proc_name.clone(),
+            source_file: after_runtime.source_file.clone(),
+
+            before_count: 0,
+            after_count: after_runtime.count,
+            before_per_round: 0.0,
+            after_per_round: per_round(after_runtime.count, after),
+        });
+    }
+
+    introduced.sort_by(|a, b| {
+        b.after_count
+            .cmp(&a.after_count)
+            .then_with(|| a.message.cmp(&b.message))
+    });
+    disappeared.sort_by(|a, b| {
+        b.before_count
+            .cmp(&a.before_count)
+            .then_with(|| a.message.cmp(&b.message))
+    });
+    changed.sort_by(|a, b| {
+        b.change()
+            .total_cmp(&a.change())
+            .then_with(|| a.message.cmp(&b.message))
+    });
+
+    RuntimeDiff {
+        before: Side {
+            rounds: before.rounds,
+            total_count: before.total_count,
+        },
+        after: Side {
+            rounds: after.rounds,
+            total_count: after.total_count,
+        },
+
+        introduced,
+        disappeared,
+        changed,
+    }
+}
+
+impl RuntimeDiff {
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "Before: {} round(s), {} runtime(s)",
+                self.before.rounds, self.before.total_count
+            ),
+            format!(
+                "After: {} round(s), {} runtime(s)",
+                self.after.rounds, self.after.total_count
+            ),
+        ];
+
+        for (title, runtimes) in [
+            ("Introduced", &self.introduced),
+            ("Disappeared", &self.disappeared),
+            ("Changed", &self.changed),
+        ] {
+            lines.push("".to_owned());
+            lines.push(format!("** {title} ({}) **", runtimes.len()));
+
+            for runtime in runtimes {
+                lines.push("".to_owned());
+                lines.push(format!(
+                    "{} -> {} time(s) ({:.1} -> {:.1} per round)",
+                    runtime.before_count,
+                    runtime.after_count,
+                    runtime.before_per_round,
+                    runtime.after_per_round
+                ));
+                lines.push(format!("runtime error: {}", runtime.message));
+                lines.push(format!("proc name: {}", runtime.proc_name));
+
+                if let Some(source_file) = &runtime.source_file {
+                    lines.push(format!("  source file: {source_file}"));
+                }
+            }
+        }
+
+        lines.push("".to_owned());
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::runtimes::CondenseOptions;
+
+    fn runtimes(runtimes: &[(&str, u64)]) -> String {
+        let mut contents = String::new();
+        for (message, count) in runtimes {
+            for _ in 0..*count {
+                contents.push_str(&format!(
+                    "[2023-11-01 12:00:00.000] runtime error: {message}\n"
+                ));
+                contents.push_str(" - proc name: foo (/datum/proc/foo)\n");
+                contents.push_str(" -   source file: code/foo.dm,12\n");
+                contents.push_str(" -   usr: null\n -   src: null\n");
+            }
+        }
+        contents
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut before = RuntimeAggregate::new(CondenseOptions::default());
+        before.add_round(
+            100,
+            &runtimes(&[("gone", 3), ("same", 10), ("spiked", 5), ("noise", 1)]),
+        );
+
+        let mut after = RuntimeAggregate::new(CondenseOptions::default());
+        after.add_round(
+            101,
+            &runtimes(&[("new", 2), ("same", 11), ("spiked", 50), ("noise", 2)]),
+        );
+
+        let diff = serde_json::to_value(diff_runtimes(&before, &after, DiffThresholds::default()))
+            .unwrap();
+
+        assert_eq!(diff["introduced"].as_array().unwrap().len(), 1);
+        assert_eq!(diff["introduced"][0]["message"], "new");
+        assert_eq!(diff["disappeared"].as_array().unwrap().len(), 1);
+        assert_eq!(diff["disappeared"][0]["message"], "gone");
+        assert_eq!(diff["changed"].as_array().unwrap().len(), 1);
+        assert_eq!(diff["changed"][0]["message"], "spiked");
+        assert_eq!(diff["changed"][0]["before_count"], 5);
+        assert_eq!(diff["changed"][0]["after_count"], 50);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, iter::Peekable, sync::LazyLock};
+
+use regex::Regex;
+
+use super::{censor_string_output, RE_RUNTIME_ERROR_START};
+
+static RE_RUNTIME_FIELD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ \-   (.+?): (.+)$").unwrap());
+
+static RE_RUNTIME_CALL_STACK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ \-   call stack:\s*$").unwrap());
+
+static RE_RUNTIME_FRAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^ \- (.+)$").unwrap());
+
+// Everything in a runtime other than its first line and proc name
+#[derive(Debug, Default, serde::Serialize)]
+pub(super) struct RuntimeDetails<'a> {
+    // The rest of the message, for runtimes that go over multiple lines
+    pub(super) message_lines: Vec<&'a str>,
+
+    // "source file", "usr", "src", "src.loc", and whatever else BYOND decides to add
+    pub(super) fields: BTreeMap<&'a str, &'a str>,
+
+    pub(super) call_stack: Vec<CallStackFrame<'a>>,
+
+    // Anything that didn't look like a field or a frame, as it was in the log
+    pub(super) unrecognized: Vec<&'a str>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(super) struct CallStackFrame<'a> {
+    // "/mob/living/carbon/human (/mob/living/carbon/human)"
+    pub(super) src: Option<&'a str>,
+
+    // "Life(20, 1)"
+    pub(super) proc: &'a str,
+}
+
+impl<'a> RuntimeDetails<'a> {
+    pub(super) fn field(&self, name: &str) -> Option<&'a str> {
+        self.fields.get(name).copied()
+    }
+}
+
+// Reads from right after the proc name line up to the next runtime
+pub(super) fn read_details<'a>(
+    lines: &mut Peekable<impl Iterator<Item = &'a str>>,
+    message_lines: Vec<&'a str>,
+) -> RuntimeDetails<'a> {
+    let mut details = RuntimeDetails {
+        message_lines,
+        ..Default::default()
+    };
+
+    let mut in_call_stack = false;
+
+    while let Some(&line) = lines.peek() {
+        if RE_RUNTIME_ERROR_START.is_match(line) {
+            break;
+        }
+
+        lines.next();
+        let line = censor_string_output(line);
+
+        if RE_RUNTIME_CALL_STACK.is_match(line) {
+            in_call_stack = true;
+            continue;
+        }
+
+        if !in_call_stack {
+            if let Some(field) = RE_RUNTIME_FIELD.captures(line) {
+                details
+                    .fields
+                    .entry(field.get(1).unwrap().as_str())
+                    .or_insert(field.get(2).unwrap().as_str());
+                continue;
+            }
+        }
+
+        if in_call_stack {
+            if let Some(frame) = RE_RUNTIME_FRAME.captures(line) {
+                let frame = frame.get(1).unwrap().as_str();
+
+                details.call_stack.push(match frame.split_once(": ") {
+                    Some((src, proc)) => CallStackFrame {
+                        src: Some(src),
+                        proc,
+                    },
+                    None => CallStackFrame {
+                        src: None,
+                        proc: frame,
+                    },
+                });
+                continue;
+            }
+        }
+
+        details.unrecognized.push(line);
+    }
+
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_details() {
+        let runtime = [
+            " -   source file: code/foo.dm,12",
+            " -   usr: null",
+            " -   src: /datum (/datum)",
+            " -   src.loc: null",
+            " -   extra field: something",
+            " -   leaky field: Cannot read \"secret\".len",
+            " -   call stack:",
+            " - /datum (/datum): foo()",
+            " - /datum (/datum): bar(1, 2)",
+            " - ...",
+            "something else entirely",
+            "[2023-11-01 12:00:01.000] runtime error: the next one",
+        ]
+        .join("\n");
+
+        let mut lines = runtime.lines().peekable();
+        let details = read_details(&mut lines, vec!["second line of the message"]);
+
+        assert_eq!(details.message_lines, ["second line of the message"]);
+        assert_eq!(details.field("source file"), Some("code/foo.dm,12"));
+        assert_eq!(details.field("src.loc"), Some("null"));
+        assert_eq!(details.field("extra field"), Some("something"));
+
+        let call_stack = serde_json::to_value(&details.call_stack).unwrap();
+        assert_eq!(
+            call_stack,
+            serde_json::json!([
+                { "src": "/datum (/datum)", "proc": "foo()" },
+                { "src": "/datum (/datum)", "proc": "bar(1, 2)" },
+                { "src": null, "proc": "..." },
+            ])
+        );
+
+        assert_eq!(
+            details.unrecognized,
+            ["-censored (string output)", "something else entirely"]
+        );
+        assert_eq!(
+            lines.next(),
+            Some("[2023-11-01 12:00:01.000] runtime error: the next one")
+        );
+    }
+}
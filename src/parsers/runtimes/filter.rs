@@ -0,0 +1,234 @@
+use globset::{Glob, GlobMatcher};
+
+use super::{without_line_number, CondensedRuntime};
+
+// Which of the condensed runtimes to show, and in what order
+#[derive(Clone, Debug, Default)]
+pub struct RuntimeFilter {
+    pub min_count: Option<u64>,
+    pub limit: Option<usize>,
+    pub proc: Option<ProcPattern>,
+
+    // Prefix, so a whole folder can be picked
+    pub source_file: Option<String>,
+
+    // Substring of the message, or of the template when normalizing
+    pub message: Option<String>,
+
+    pub sort: RuntimeSort,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum RuntimeSort {
+    // Most common first
+    #[default]
+    Count,
+    Proc,
+    File,
+    FirstSeen,
+}
+
+impl RuntimeSort {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "proc" => Some(Self::Proc),
+            "file" => Some(Self::File),
+            "first_seen" => Some(Self::FirstSeen),
+            _ => None,
+        }
+    }
+}
+
+// Matched against both "foo (/datum/proc/foo)" and "/datum/proc/foo"
+#[derive(Clone, Debug)]
+pub enum ProcPattern {
+    Prefix(String),
+    Glob(GlobMatcher),
+}
+
+impl ProcPattern {
+    // Anything that isn't a valid glob is a prefix
+    pub fn new(pattern: &str) -> Self {
+        if pattern.contains(['*', '?', '[', '{']) {
+            if let Ok(glob) = Glob::new(pattern) {
+                return Self::Glob(glob.compile_matcher());
+            }
+        }
+
+        Self::Prefix(pattern.to_owned())
+    }
+
+    fn is_match(&self, proc_name: &str) -> bool {
+        let path = proc_name
+            .rsplit_once(" (")
+            .and_then(|(_, path)| path.strip_suffix(')'));
+
+        [Some(proc_name), path]
+            .into_iter()
+            .flatten()
+            .any(|candidate| match self {
+                Self::Prefix(prefix) => candidate.starts_with(prefix.as_str()),
+                Self::Glob(glob) => glob.is_match(candidate),
+            })
+    }
+}
+
+impl RuntimeFilter {
+    // Sorting doesn't count, there are only so many ways to do it
+    pub fn is_empty(&self) -> bool {
+        self.min_count.is_none()
+            && self.limit.is_none()
+            && self.proc.is_none()
+            && self.source_file.is_none()
+            && self.message.is_none()
+    }
+
+    // Runtimes come in sorted by count
+    pub(super) fn apply<'a>(&self, runtimes: &mut Vec<CondensedRuntime<'a>>) {
+        runtimes.retain(|runtime| {
+            self.min_count
+                .is_none_or(|min_count| runtime.value.count >= min_count)
+                && self
+                    .proc
+                    .as_ref()
+                    .is_none_or(|proc| proc.is_match(runtime.key.proc_name))
+                && self.source_file.as_ref().is_none_or(|source_file| {
+                    runtime
+                        .value
+                        .source_file
+                        .is_some_and(|file| file.starts_with(source_file.as_str()))
+                })
+                && self
+                    .message
+                    .as_ref()
+                    .is_none_or(|message| runtime.key.message.contains(message.as_str()))
+        });
+
+        // Stable, so ties stay most common first
+        match self.sort {
+            RuntimeSort::Count => {}
+            RuntimeSort::Proc => runtimes.sort_by(|a, b| a.key.proc_name.cmp(b.key.proc_name)),
+            RuntimeSort::File => runtimes.sort_by(|a, b| {
+                a.value
+                    .source_file
+                    .map(without_line_number)
+                    .cmp(&b.value.source_file.map(without_line_number))
+            }),
+            RuntimeSort::FirstSeen => {
+                runtimes.sort_by(|a, b| a.value.first_seen.cmp(b.value.first_seen))
+            }
+        }
+
+        if let Some(limit) = self.limit {
+            runtimes.truncate(limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::runtimes::{condense_runtimes_to_json, CondenseOptions};
+
+    fn runtimes() -> String {
+        let mut contents = String::new();
+        for (second, message, proc, source_file, count) in [
+            (
+                5,
+                "Cannot read null.x",
+                "foo (/datum/proc/foo)",
+                "code/a/foo.dm,1",
+                3,
+            ),
+            (1, "bad index", "bar (/mob/proc/bar)", "code/b/bar.dm,2", 2),
+            (3, "bad del", "baz (/datum/proc/baz)", "code/a/baz.dm,3", 1),
+        ] {
+            for _ in 0..count {
+                contents.push_str(&format!(
+                    "[2023-11-01 12:00:0{second}.000] runtime error: {message}\n"
+                ));
+                contents.push_str(&format!(" - proc name: {proc}\n"));
+                contents.push_str(&format!(" -   source file: {source_file}\n"));
+                contents.push_str(" -   usr: null\n -   src: null\n");
+            }
+        }
+        contents
+    }
+
+    fn messages(filter: RuntimeFilter) -> Vec<String> {
+        let condensed = condense_runtimes_to_json(
+            &runtimes(),
+            CondenseOptions {
+                filter,
+                ..Default::default()
+            },
+        );
+
+        condensed["runtimes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|runtime| runtime["message"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(
+            messages(RuntimeFilter::default()),
+            ["Cannot read null.x", "bad index", "bad del"]
+        );
+
+        assert_eq!(
+            messages(RuntimeFilter {
+                min_count: Some(2),
+                ..Default::default()
+            }),
+            ["Cannot read null.x", "bad index"]
+        );
+
+        assert_eq!(
+            messages(RuntimeFilter {
+                limit: Some(1),
+                sort: RuntimeSort::FirstSeen,
+                ..Default::default()
+            }),
+            ["bad index"]
+        );
+
+        assert_eq!(
+            messages(RuntimeFilter {
+                proc: Some(ProcPattern::new("/datum/proc/")),
+                sort: RuntimeSort::Proc,
+                ..Default::default()
+            }),
+            ["bad del", "Cannot read null.x"]
+        );
+
+        assert_eq!(
+            messages(RuntimeFilter {
+                proc: Some(ProcPattern::new("*/proc/ba?")),
+                ..Default::default()
+            }),
+            ["bad index", "bad del"]
+        );
+
+        assert_eq!(
+            messages(RuntimeFilter {
+                source_file: Some("code/a/".to_owned()),
+                sort: RuntimeSort::File,
+                ..Default::default()
+            }),
+            ["bad del", "Cannot read null.x"]
+        );
+
+        assert_eq!(
+            messages(RuntimeFilter {
+                message: Some("bad".to_owned()),
+                ..Default::default()
+            }),
+            ["bad index", "bad del"]
+        );
+    }
+}
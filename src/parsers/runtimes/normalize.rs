@@ -0,0 +1,62 @@
+use std::{borrow::Cow, sync::LazyLock};
+
+use regex::Regex;
+
+// Placeholders for the parts of a runtime message that change between otherwise identical runtimes.
+// Coordinates go before numbers, and refs before both, so they don't get picked apart.
+static REPLACEMENTS: LazyLock<[(Regex, &str); 5]> = LazyLock::new(|| {
+    [
+        (Regex::new(r"\[0x[0-9a-fA-F]+\]").unwrap(), "[<ref>]"),
+        (Regex::new(r"\b0x[0-9a-fA-F]+\b").unwrap(), "<ref>"),
+        (
+            Regex::new(r"\(\s*\d+\s*,\s*\d+\s*,\s*\d+\s*\)").unwrap(),
+            "<coords>",
+        ),
+        (Regex::new(r#""[^"]*""#).unwrap(), "<string>"),
+        (Regex::new(r"\b\d+(?:\.\d+)?\b").unwrap(), "<number>"),
+    ]
+});
+
+// "bad index 5 on [0x2001234] at (10, 20, 2)" -> "bad index <number> on [<ref>] at <coords>"
+pub fn normalize_message(message: &str) -> Cow<'_, str> {
+    let mut message = Cow::Borrowed(message);
+
+    for (regex, placeholder) in REPLACEMENTS.iter() {
+        if let Cow::Owned(replaced) = regex.replace_all(&message, *placeholder) {
+            message = Cow::Owned(replaced);
+        }
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_message() {
+        for (message, expected) in [
+            ("Cannot read null.x", "Cannot read null.x"),
+            (
+                "Cannot execute null.Destroy() on [0x2001234]",
+                "Cannot execute null.Destroy() on [<ref>]",
+            ),
+            (
+                "list index out of bounds at (104, 87, 2), index 12",
+                "list index out of bounds at <coords>, index <number>",
+            ),
+            (
+                r#"the human "John Smith" tried to move 1.5 tiles"#,
+                "the human <string> tried to move <number> tiles",
+            ),
+            ("mob123 has ref 0x21003f4", "mob123 has ref <ref>"),
+            (
+                "undefined variable /datum/var/x2",
+                "undefined variable /datum/var/x2",
+            ),
+        ] {
+            assert_eq!(normalize_message(message), expected, "{message}");
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use super::{get_filtered_runtimes, without_line_number, CondenseOptions, CondensedRuntime};
+use crate::parsers::{identifier_filtering::filter_identifiers, ip_filtering::filter_ips};
+
+// The same runtimes as the flat list, nested by source file and then proc,
+// so a subsystem that's falling over stands out.
+#[derive(serde::Serialize)]
+struct RuntimeTree<'a> {
+    total_count: u64,
+    source_files: Vec<SourceFileNode<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct SourceFileNode<'a> {
+    // Without the line number. None for runtimes that didn't say.
+    source_file: Option<&'a str>,
+    count: u64,
+    procs: Vec<ProcNode<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct ProcNode<'a> {
+    proc_name: &'a str,
+    count: u64,
+    runtimes: Vec<CondensedRuntime<'a>>,
+}
+
+pub fn condense_runtimes_to_tree_string(contents: &str, options: CondenseOptions) -> String {
+    let contents = filter_ips(contents);
+    let contents = filter_identifiers(&contents);
+
+    let condensed_runtimes = get_filtered_runtimes(&contents, &options);
+    let unique_count = condensed_runtimes.runtimes.len();
+    let tree = build_tree(condensed_runtimes.total_count, condensed_runtimes.runtimes);
+
+    let mut lines = vec![
+        "Note: Runtimes are grouped by the source file of the FIRST of the identical runtimes."
+            .to_owned(),
+        "".to_owned(),
+        format!("Total unique runtimes: {unique_count}"),
+        format!("Total runtimes: {}", tree.total_count),
+    ];
+
+    for source_file in tree.source_files {
+        lines.push("".to_owned());
+        lines.push(format!(
+            "{} ({} time(s))",
+            source_file.source_file.unwrap_or("(unknown source file)"),
+            source_file.count
+        ));
+
+        for proc in source_file.procs {
+            lines.push(format!("  {} ({} time(s))", proc.proc_name, proc.count));
+
+            for runtime in proc.runtimes {
+                lines.push(format!(
+                    "    {}x runtime error: {}",
+                    runtime.value.count, runtime.key.message
+                ));
+            }
+        }
+    }
+
+    lines.push("".to_owned());
+
+    lines.join("\n")
+}
+
+pub fn condense_runtimes_to_tree_json(
+    contents: &str,
+    options: CondenseOptions,
+) -> serde_json::Value {
+    let contents = filter_ips(contents);
+    let contents = filter_identifiers(&contents);
+
+    let condensed_runtimes = get_filtered_runtimes(&contents, &options);
+
+    serde_json::to_value(build_tree(
+        condensed_runtimes.total_count,
+        condensed_runtimes.runtimes,
+    ))
+    .expect("couldn't serialize json")
+}
+
+fn build_tree<'a>(total_count: u64, runtimes: Vec<CondensedRuntime<'a>>) -> RuntimeTree<'a> {
+    let mut source_files: HashMap<Option<&str>, HashMap<&str, Vec<CondensedRuntime>>> =
+        HashMap::new();
+
+    // Runtimes come in sorted by count, and stay that way inside each proc
+    for runtime in runtimes {
+        source_files
+            .entry(runtime.value.source_file.map(without_line_number))
+            .or_default()
+            .entry(runtime.key.proc_name)
+            .or_default()
+            .push(runtime);
+    }
+
+    let mut source_files: Vec<SourceFileNode> = source_files
+        .into_iter()
+        .map(|(source_file, procs)| {
+            let mut procs: Vec<ProcNode> = procs
+                .into_iter()
+                .map(|(proc_name, runtimes)| ProcNode {
+                    proc_name,
+                    count: runtimes.iter().map(|runtime| runtime.value.count).sum(),
+                    runtimes,
+                })
+                .collect();
+            procs.sort_by(|a, b| b.count.cmp(&a.count).then(a.proc_name.cmp(b.proc_name)));
+
+            SourceFileNode {
+                source_file,
+                count: procs.iter().map(|proc| proc.count).sum(),
+                procs,
+            }
+        })
+        .collect();
+    source_files.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then(a.source_file.cmp(&b.source_file))
+    });
+
+    RuntimeTree {
+        total_count,
+        source_files,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUNTIMES: &str = "\
+[2023-11-01 12:00:00.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: /datum (/datum)
+[2023-11-01 12:00:01.000] runtime error: Cannot read null.x
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,12
+ -   usr: null
+ -   src: /datum (/datum)
+[2023-11-01 12:00:02.000] runtime error: list index out of bounds
+ - proc name: foo (/datum/proc/foo)
+ -   source file: code/foo.dm,20
+ -   usr: null
+ -   src: /datum (/datum)
+[2023-11-01 12:00:03.000] runtime error: bad del
+ - proc name: other (/datum/proc/other)
+ -   source file: code/foo.dm,40
+ -   usr: null
+ -   src: /datum (/datum)
+[2023-11-01 12:00:04.000] runtime error: division by zero
+ - proc name: bar (/datum/proc/bar)
+ -   source file: code/bar.dm,5
+ -   usr: null
+ -   src: null
+";
+
+    #[test]
+    fn test_tree() {
+        let tree = condense_runtimes_to_tree_json(RUNTIMES, CondenseOptions::default());
+
+        assert_eq!(tree["total_count"], 5);
+
+        let foo = &tree["source_files"][0];
+        assert_eq!(foo["source_file"], "code/foo.dm");
+        assert_eq!(foo["count"], 4);
+        assert_eq!(foo["procs"][0]["proc_name"], "foo (/datum/proc/foo)");
+        assert_eq!(foo["procs"][0]["count"], 3);
+        assert_eq!(foo["procs"][0]["runtimes"][0]["count"], 2);
+        assert_eq!(foo["procs"][1]["count"], 1);
+
+        let bar = &tree["source_files"][1];
+        assert_eq!(bar["source_file"], "code/bar.dm");
+        assert_eq!(bar["count"], 1);
+
+        let text = condense_runtimes_to_tree_string(RUNTIMES, CondenseOptions::default());
+        assert!(text.contains(
+            "code/foo.dm (4 time(s))\n  foo (/datum/proc/foo) (3 time(s))\n    2x runtime error: Cannot read null.x\n"
+        ));
+    }
+}
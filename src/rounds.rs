@@ -0,0 +1,98 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::app_state::AppState;
+
+#[derive(Debug)]
+pub struct Round {
+    pub id: u64,
+    pub path: PathBuf,
+}
+
+// Every finished round folder under the directory, however deep, sorted by round ID.
+// A round folder is anything named round-<id>.
+// Stops looking after finding more than the limit, so a result longer than it means there were too many.
+pub async fn finished_rounds(
+    state: &AppState,
+    directory: &Path,
+    limit: usize,
+) -> eyre::Result<Vec<Round>> {
+    let mut rounds = Vec::new();
+    let mut directories = vec![directory.to_owned()];
+
+    while let Some(directory) = directories.pop() {
+        if rounds.len() > limit {
+            break;
+        }
+
+        if let Some(id) = round_id(&directory) {
+            if !state.path_is_ongoing_round(&directory).await? {
+                rounds.push(Round {
+                    id,
+                    path: directory,
+                });
+            }
+
+            continue;
+        }
+
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                directories.push(entry.path());
+            }
+        }
+    }
+
+    rounds.sort_by_key(|round| round.id);
+
+    Ok(rounds)
+}
+
+pub fn round_id(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("round-")?
+        .parse()
+        .ok()
+}
+
+// Changes whenever one of the rounds' copies of the file does, or when rounds are added.
+// Used in place of the modified time of one source file for output built from many.
+pub fn fingerprint(rounds: &[Round], file_name: &str) -> io::Result<String> {
+    files_fingerprint(rounds.iter().map(|round| round.path.join(file_name)))
+}
+
+// The same, for any set of files. Missing files count as a change when they show up.
+pub fn files_fingerprint(paths: impl IntoIterator<Item = PathBuf>) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    for path in paths {
+        hasher.update(path.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+
+        match std::fs::metadata(&path) {
+            Ok(metadata) => {
+                hasher.update(
+                    metadata
+                        .modified()?
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_nanos()
+                        .to_le_bytes(),
+                );
+                hasher.update(metadata.len().to_le_bytes());
+            }
+
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(format!("{:.16x}", hasher.finalize()))
+}
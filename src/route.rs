@@ -1,16 +1,78 @@
-use std::{collections::HashMap, io::ErrorKind, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
 
 use axum::{
     extract::{OriginalUri, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
 use serde::Serialize;
 
-use crate::{app_state::AppState, parsers::get_file_sanitization_strategy};
+use crate::{
+    app_state::AppState,
+    cache::{complete_output, stream_cached_body, stream_uncached_body},
+    conditional::{Evaluation, Validators},
+    parsers::{
+        censor_report::CensorReport,
+        game::{self, GameLogFilter, ParsedGameLogFormat},
+        get_file_sanitization_strategy,
+        round_summary::{summarize_round, ROUND_SUMMARY_SOURCES},
+        runtimes::{
+            aggregate::RuntimeAggregate,
+            count_fingerprint,
+            diff::{diff_runtimes, DiffThresholds},
+            filter::{ProcPattern, RuntimeFilter, RuntimeSort},
+            is_fingerprint, CondenseOptions,
+        },
+        SanitizeOptions, Strategy,
+    },
+    rounds::{self, Round},
+    search::SearchQuery,
+};
 
 pub const RUNTIME_CONDENSED_JSON: &str = "runtime.condensed.json";
 pub const RUNTIME_CONDENSED_TXT: &str = "runtime.condensed.txt";
+pub const RUNTIME_CONDENSED_MD: &str = "runtime.condensed.md";
+pub const RUNTIME_CONDENSED_CSV: &str = "runtime.condensed.csv";
+pub const RUNTIME_CONDENSED_TREE_JSON: &str = "runtime.condensed.tree.json";
+pub const RUNTIME_CONDENSED_TREE_TXT: &str = "runtime.condensed.tree.txt";
+
+// Generated from runtime.log, and listed next to it
+const RUNTIME_PRETEND_FILES: [&str; 6] = [
+    RUNTIME_CONDENSED_JSON,
+    RUNTIME_CONDENSED_TXT,
+    RUNTIME_CONDENSED_MD,
+    RUNTIME_CONDENSED_CSV,
+    RUNTIME_CONDENSED_TREE_JSON,
+    RUNTIME_CONDENSED_TREE_TXT,
+];
+
+// Merges every finished round under whatever directory it's in
+pub const RUNTIME_AGGREGATE_JSON: &str = "runtime.aggregate.json";
+
+// Routes that read every round under a folder refuse folders with more than this.
+// A month of one server is well under it.
+const MAX_FOLDER_ROUNDS: usize = 2000;
+
+// The sanitized game.log, split into fields, listed next to it
+pub const GAME_PARSED_JSON: &str = "game.parsed.json";
+pub const GAME_PARSED_NDJSON: &str = "game.parsed.ndjson";
+
+// An overview of the round, listed in every round folder
+pub const ROUND_SUMMARY_JSON: &str = "round.summary.json";
+pub const ROUND_SUMMARY_HTML: &str = "round.summary.html";
+
+pub const CENSOR_REPORT_SUFFIX: &str = ".censor-report.json";
+
+// Finished rounds never change, and the ETag changes if the sanitization policy does
+const FILE_CACHE_CONTROL: &str = "public, max-age=31536000";
+
+// Directories get new rounds, and ongoing rounds show up once they finish
+const DIRECTORY_CACHE_CONTROL: &str = "public, max-age=60";
 
 const NOT_FOUND: (StatusCode, &str) = (StatusCode::NOT_FOUND, "couldn't find that path");
 
@@ -26,6 +88,7 @@ pub async fn get(
     State(state): State<Arc<AppState>>,
     OriginalUri(uri): OriginalUri,
     Query(params): Query<HashMap<String, String>>,
+    request_headers: HeaderMap,
 ) -> Result<impl IntoResponse, axum::response::Response> {
     let requested_path = state
         .config
@@ -37,6 +100,12 @@ pub async fn get(
         return Ok((StatusCode::FORBIDDEN, "attempted path traversal").into_response());
     }
 
+    // Without any trailing slash, since game.log/ is told apart from game.log by the URI
+    let requested_path = match (requested_path.parent(), requested_path.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => requested_path,
+    };
+
     match state.path_is_ongoing_round(&requested_path).await {
         Ok(true) => {
             tracing::debug!("blocking access to ongoing round");
@@ -56,30 +125,304 @@ pub async fn get(
 
     // Pretend files
     match requested_path.file_name().and_then(std::ffi::OsStr::to_str) {
-        name @ Some(RUNTIME_CONDENSED_TXT) | name @ Some(RUNTIME_CONDENSED_JSON) => {
+        Some(RUNTIME_AGGREGATE_JSON) => {
+            let directory = requested_path
+                .parent()
+                .expect("pretend file has no parent")
+                .to_owned();
+
+            if !can_aggregate_runtimes(&state, &directory)
+                || !std::fs::metadata(&directory).is_ok_and(|metadata| metadata.is_dir())
+            {
+                return Ok(NOT_FOUND.into_response());
+            }
+
+            let options = condense_options(&params);
+            let rounds = folder_rounds(&state, &directory).await?;
+
+            let fingerprint = rounds::fingerprint(&rounds, "runtime.log").map_err(|error| {
+                error_to_response(
+                    error,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "couldn't get metadata of runtime logs",
+                )
+            })?;
+
+            return Ok(sanitized_file_response(
+                Arc::clone(&state),
+                &request_headers,
+                directory,
+                Variant::Cached(format!(
+                    "{RUNTIME_AGGREGATE_JSON} {options:?} {fingerprint}"
+                )),
+                "application/json",
+                DIRECTORY_CACHE_CONTROL,
+                move |_, writer| {
+                    serde_json::to_writer(writer, &aggregate_runtimes(&rounds, &options)?.to_json())
+                        .map_err(std::io::Error::from)
+                },
+            )
+            .await);
+        }
+
+        Some(name) if RUNTIME_PRETEND_FILES.contains(&name) => {
             let runtimes_file = requested_path.with_file_name("runtime.log");
-            let runtimes_contents = std::fs::read_to_string(runtimes_file).map_err(|error| {
-                error_to_response(error, StatusCode::NOT_FOUND, "couldn't find runtime.log")
+            if let Err(error) = std::fs::metadata(&runtimes_file) {
+                return Err(error_to_response(
+                    error,
+                    StatusCode::NOT_FOUND,
+                    "couldn't find runtime.log",
+                ));
+            }
+
+            let name = name.to_owned();
+            let options = CondenseOptions {
+                filter: runtime_filter(&params),
+                ..condense_options(&params)
+            };
+
+            return Ok(sanitized_file_response(
+                Arc::clone(&state),
+                &request_headers,
+                runtimes_file.clone(),
+                if options.filter.is_empty() {
+                    Variant::Cached(format!("{name} {options:?}"))
+                } else {
+                    Variant::Uncached(format!("{name} {options:?}"))
+                },
+                match name.rsplit_once('.').map(|(_, extension)| extension) {
+                    Some("txt") => "text/plain",
+                    Some("md") => "text/markdown",
+                    Some("csv") => "text/csv",
+                    _ => "application/json",
+                },
+                FILE_CACHE_CONTROL,
+                move |_, writer| {
+                    use crate::parsers::runtimes::{self, tree};
+
+                    let runtimes_contents = std::fs::read_to_string(runtimes_file)?;
+
+                    match name.as_str() {
+                        RUNTIME_CONDENSED_TXT => writer.write_all(
+                            runtimes::condense_runtimes_to_string(&runtimes_contents, options)
+                                .as_bytes(),
+                        ),
+
+                        RUNTIME_CONDENSED_JSON => serde_json::to_writer(
+                            writer,
+                            &runtimes::condense_runtimes_to_json(&runtimes_contents, options),
+                        )
+                        .map_err(std::io::Error::from),
+
+                        RUNTIME_CONDENSED_MD => writer.write_all(
+                            runtimes::condense_runtimes_to_markdown(&runtimes_contents, options)
+                                .as_bytes(),
+                        ),
+
+                        RUNTIME_CONDENSED_CSV => writer.write_all(
+                            runtimes::condense_runtimes_to_csv(&runtimes_contents, options)
+                                .as_bytes(),
+                        ),
+
+                        RUNTIME_CONDENSED_TREE_TXT => writer.write_all(
+                            tree::condense_runtimes_to_tree_string(&runtimes_contents, options)
+                                .as_bytes(),
+                        ),
+
+                        RUNTIME_CONDENSED_TREE_JSON => serde_json::to_writer(
+                            writer,
+                            &tree::condense_runtimes_to_tree_json(&runtimes_contents, options),
+                        )
+                        .map_err(std::io::Error::from),
+
+                        _ => unreachable!("not a runtime pretend file: {name}"),
+                    }
+                },
+            )
+            .await);
+        }
+
+        Some(name @ (GAME_PARSED_JSON | GAME_PARSED_NDJSON)) => {
+            let game_log = requested_path.with_file_name("game.log");
+            if get_file_sanitization_strategy(&state.rules, &game_log) != Some(Strategy::Game) {
+                return Ok(NOT_FOUND.into_response());
+            }
+
+            let file = std::fs::File::open(&game_log).map_err(|error| {
+                error_to_response(error, StatusCode::NOT_FOUND, "couldn't find game.log")
             })?;
 
-            if name == Some(RUNTIME_CONDENSED_TXT) {
-                return Ok((
-                    StatusCode::OK,
-                    headers("text/plain"),
-                    crate::parsers::runtimes::condense_runtimes_to_string(&runtimes_contents),
-                )
-                    .into_response());
-            } else if name == Some(RUNTIME_CONDENSED_JSON) {
-                return Ok((
-                    StatusCode::OK,
-                    headers("application/json"),
-                    crate::parsers::runtimes::condense_runtimes_to_json(&runtimes_contents)
-                        .to_string(),
-                )
-                    .into_response());
+            let format = if name == GAME_PARSED_JSON {
+                ParsedGameLogFormat::Json
             } else {
-                unreachable!();
+                ParsedGameLogFormat::Ndjson
+            };
+            let filter = game_log_filter(&params);
+
+            return Ok(sanitized_file_response(
+                Arc::clone(&state),
+                &request_headers,
+                game_log,
+                if filter.is_empty() {
+                    Variant::Cached(format!("{format:?} {filter:?}"))
+                } else {
+                    Variant::Uncached(format!("{format:?} {filter:?}"))
+                },
+                match format {
+                    ParsedGameLogFormat::Json => "application/json",
+                    ParsedGameLogFormat::Ndjson => "application/x-ndjson",
+                },
+                FILE_CACHE_CONTROL,
+                move |state, writer| {
+                    game::write_parsed_game_log(
+                        &state.rules,
+                        std::io::BufReader::new(file),
+                        writer,
+                        format,
+                        &filter,
+                    )
+                },
+            )
+            .await);
+        }
+
+        Some(name @ (ROUND_SUMMARY_JSON | ROUND_SUMMARY_HTML)) => {
+            let round_path = requested_path
+                .parent()
+                .expect("file name without a parent")
+                .to_owned();
+
+            if rounds::round_id(&round_path).is_none()
+                || !std::fs::metadata(&round_path).is_ok_and(|metadata| metadata.is_dir())
+            {
+                return Ok(NOT_FOUND.into_response());
             }
+
+            let fingerprint = rounds::files_fingerprint(
+                ROUND_SUMMARY_SOURCES.map(|source| round_path.join(source)),
+            )
+            .map_err(|error| {
+                error_to_response(
+                    error,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "couldn't get metadata of round logs",
+                )
+            })?;
+
+            let as_html = name == ROUND_SUMMARY_HTML;
+
+            return Ok(sanitized_file_response(
+                Arc::clone(&state),
+                &request_headers,
+                round_path.clone(),
+                Variant::Cached(format!("{name} {fingerprint}")),
+                if as_html {
+                    "text/html"
+                } else {
+                    "application/json"
+                },
+                FILE_CACHE_CONTROL,
+                move |state, writer| {
+                    let summary = summarize_round(&state.rules, &round_path)?;
+
+                    if as_html {
+                        writer.write_all(summary.to_html().as_bytes())
+                    } else {
+                        serde_json::to_writer(writer, &summary).map_err(std::io::Error::from)
+                    }
+                },
+            )
+            .await);
+        }
+
+        // game.log/SAY.log and friends, same as game.log?category=SAY
+        Some(name)
+            if name.ends_with(".log")
+                && requested_path
+                    .parent()
+                    .and_then(std::path::Path::file_name)
+                    .is_some_and(|parent| parent == "game.log") =>
+        {
+            let game_log = requested_path
+                .parent()
+                .expect("checked for a parent")
+                .to_owned();
+
+            if get_file_sanitization_strategy(&state.rules, &game_log) != Some(Strategy::Game) {
+                return Ok(NOT_FOUND.into_response());
+            }
+
+            let file = std::fs::File::open(&game_log).map_err(|error| {
+                error_to_response(error, StatusCode::NOT_FOUND, "couldn't find game.log")
+            })?;
+
+            let mut game_filter = game_log_filter(&params);
+            game_filter.categories = vec![name
+                .strip_suffix(".log")
+                .expect("ends_with lied")
+                .to_owned()];
+
+            let options = SanitizeOptions {
+                game_filter,
+                ..Default::default()
+            };
+
+            return Ok(sanitized_file_response(
+                Arc::clone(&state),
+                &request_headers,
+                game_log,
+                // Anything can be asked for as a category
+                Variant::Uncached(format!("{:?} {options:?}", Strategy::Game)),
+                "text/plain",
+                FILE_CACHE_CONTROL,
+                move |state, writer| {
+                    Strategy::Game.sanitize(
+                        &state.rules,
+                        &options,
+                        std::io::BufReader::new(file),
+                        writer,
+                        &mut CensorReport::new(Strategy::Game),
+                    )
+                },
+            )
+            .await);
+        }
+
+        Some(name) if name.ends_with(CENSOR_REPORT_SUFFIX) => {
+            let log_path = requested_path.with_file_name(
+                name.strip_suffix(CENSOR_REPORT_SUFFIX)
+                    .expect("ends_with lied"),
+            );
+
+            let Some(strategy) = get_file_sanitization_strategy(&state.rules, &log_path) else {
+                return Ok(NOT_FOUND.into_response());
+            };
+
+            let file = std::fs::File::open(&log_path).map_err(|error| {
+                error_to_response(error, StatusCode::NOT_FOUND, "couldn't find that log")
+            })?;
+
+            return Ok(sanitized_file_response(
+                Arc::clone(&state),
+                &request_headers,
+                log_path,
+                Variant::Cached(CENSOR_REPORT_SUFFIX.to_owned()),
+                "application/json",
+                FILE_CACHE_CONTROL,
+                move |state, writer| {
+                    let mut report = CensorReport::new(strategy);
+                    strategy.sanitize(
+                        &state.rules,
+                        &SanitizeOptions::default(),
+                        std::io::BufReader::new(file),
+                        &mut std::io::sink(),
+                        &mut report,
+                    )?;
+
+                    serde_json::to_writer(writer, &report).map_err(std::io::Error::from)
+                },
+            )
+            .await);
         }
 
         _ => {}
@@ -99,7 +442,12 @@ pub async fn get(
             }
         })?;
 
-    if metadata.is_dir() {
+    // game.log/, as opposed to game.log
+    let is_game_log_folder = metadata.is_file()
+        && uri.path().ends_with('/')
+        && get_file_sanitization_strategy(&state.rules, &requested_path) == Some(Strategy::Game);
+
+    if metadata.is_dir() || is_game_log_folder {
         if params.get("format").map(|v| v == "json").unwrap_or(false) {
             let items = collect_traversal_items(&state, &requested_path)
                 .await
@@ -110,60 +458,455 @@ pub async fn get(
                         "error creating traversal JSON",
                     )
                 })?;
-            Ok((
-                StatusCode::OK,
-                headers("application/json"),
+
+            Ok(contents_response(
+                &request_headers,
+                "application/json",
                 serde_json::to_string(&items).unwrap(),
-            )
-                .into_response())
+            ))
         } else {
-            Ok((
-                StatusCode::OK,
-                headers("text/html"),
-                traversal_page(&state, &requested_path)
-                    .await
-                    .map_err(|error| {
-                        error_to_response(
-                            error,
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            "error creating traversal page",
-                        )
-                    }),
-            )
-                .into_response())
+            let page = traversal_page(&state, &requested_path)
+                .await
+                .map_err(|error| {
+                    error_to_response(
+                        error,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "error creating traversal page",
+                    )
+                })?;
+
+            Ok(contents_response(&request_headers, "text/html", page))
         }
     } else if metadata.is_file() {
-        let Some(strategy) = get_file_sanitization_strategy(&requested_path) else {
+        let Some(strategy) = get_file_sanitization_strategy(&state.rules, &requested_path) else {
             return Ok(NOT_FOUND.into_response());
         };
 
-        Ok((
-            StatusCode::OK,
-            headers(
-                if requested_path.extension().and_then(std::ffi::OsStr::to_str) == Some("json") {
-                    "application/json"
-                } else {
-                    "text/plain"
-                },
-            ),
-            strategy(std::fs::read_to_string(&requested_path).map_err(|error| {
-                error_to_response(
-                    error,
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "couldn't read file",
-                )
-            })?),
+        let file = std::fs::File::open(&requested_path).map_err(|error| {
+            error_to_response(
+                error,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't read file",
+            )
+        })?;
+
+        let options = SanitizeOptions {
+            html_as_text: params.get("format").map(|v| v == "text").unwrap_or(false),
+            game_filter: game_log_filter(&params),
+        };
+
+        let content_type = strategy.content_type(&requested_path, &options);
+
+        Ok(sanitized_file_response(
+            Arc::clone(&state),
+            &request_headers,
+            requested_path,
+            if options.game_filter.is_empty() {
+                Variant::Cached(format!("{strategy:?} {options:?}"))
+            } else {
+                Variant::Uncached(format!("{strategy:?} {options:?}"))
+            },
+            content_type,
+            FILE_CACHE_CONTROL,
+            move |state, writer| {
+                let mut report = CensorReport::new(strategy);
+                strategy.sanitize(
+                    &state.rules,
+                    &options,
+                    std::io::BufReader::new(file),
+                    writer,
+                    &mut report,
+                )?;
+
+                tracing::debug!(?report, "sanitized file");
+                Ok(())
+            },
         )
-            .into_response())
+        .await)
     } else {
         Ok((StatusCode::BAD_REQUEST, "tried to access weird file").into_response())
     }
 }
 
+// ?name=true or ?name=false, with anything else meaning the default
+fn flag(params: &HashMap<String, String>, name: &str, default: bool) -> bool {
+    match params.get(name).map(String::as_str) {
+        Some("true" | "1") => true,
+        Some("false" | "0") => false,
+        _ => default,
+    }
+}
+
+// ?normalize=false&samples=false gives the same runtimes as the C++ condenser
+fn condense_options(params: &HashMap<String, String>) -> CondenseOptions {
+    CondenseOptions {
+        histogram: flag(params, "histogram", false),
+        normalize: flag(params, "normalize", true),
+        samples: flag(params, "samples", true),
+        filter: RuntimeFilter::default(),
+    }
+}
+
+// ?min_count=&limit=&proc=&source_file=&message=&sort=, with anything unparseable ignored
+fn runtime_filter(params: &HashMap<String, String>) -> RuntimeFilter {
+    RuntimeFilter {
+        min_count: params
+            .get("min_count")
+            .and_then(|min_count| min_count.parse().ok()),
+        limit: params.get("limit").and_then(|limit| limit.parse().ok()),
+        proc: params.get("proc").map(|proc| ProcPattern::new(proc)),
+        source_file: params.get("source_file").cloned(),
+        message: params.get("message").cloned(),
+        sort: params
+            .get("sort")
+            .and_then(|sort| RuntimeSort::from_name(sort))
+            .unwrap_or_default(),
+    }
+}
+
+// ?category=SAY,EMOTE&exclude=ACCESS&from=12:30:00&to=12:45:00&contains=
+fn game_log_filter(params: &HashMap<String, String>) -> GameLogFilter {
+    let list = |name: &str| -> Vec<String> {
+        params
+            .get(name)
+            .map(|list| {
+                list.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    GameLogFilter {
+        categories: list("category"),
+        exclude: list("exclude"),
+        from: params.get("from").cloned(),
+        to: params.get("to").cloned(),
+        contains: params.get("contains").cloned(),
+    }
+}
+
+// Compares the runtimes of two rounds, or two folders of rounds
+#[tracing::instrument]
+pub async fn runtime_diff(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+    request_headers: HeaderMap,
+) -> Result<impl IntoResponse, Response> {
+    let (Some(before), Some(after)) = (params.get("before"), params.get("after")) else {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "expected ?before=<round or folder>&after=<round or folder>",
+        )
+            .into_response());
+    };
+
+    let before = rounds_at(&state, before).await?;
+    let after = rounds_at(&state, after).await?;
+
+    let defaults = DiffThresholds::default();
+    let thresholds = DiffThresholds {
+        ratio: params
+            .get("ratio")
+            .and_then(|ratio| ratio.parse().ok())
+            .unwrap_or(defaults.ratio),
+        min_difference: params
+            .get("min_difference")
+            .and_then(|min_difference| min_difference.parse().ok())
+            .unwrap_or(defaults.min_difference),
+    };
+
+    let options = condense_options(&params);
+    let as_text = params.get("format").map(|v| v == "text").unwrap_or(false);
+
+    let body = tokio::task::spawn_blocking(move || {
+        let diff = diff_runtimes(
+            &aggregate_runtimes(&before, &options)?,
+            &aggregate_runtimes(&after, &options)?,
+            thresholds,
+        );
+
+        if as_text {
+            Ok(diff.to_text())
+        } else {
+            serde_json::to_string(&diff).map_err(std::io::Error::from)
+        }
+    })
+    .await
+    .map_err(std::io::Error::other)
+    .flatten()
+    .map_err(|error| {
+        error_to_response(
+            error,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "couldn't diff runtimes",
+        )
+    })?;
+
+    Ok(contents_response(
+        &request_headers,
+        if as_text {
+            "text/plain"
+        } else {
+            "application/json"
+        },
+        body,
+    ))
+}
+
+// Which released rounds have lines matching ?q=, optionally only on ?server= and from round ?from= on
+#[tracing::instrument]
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+    request_headers: HeaderMap,
+) -> Result<impl IntoResponse, Response> {
+    let Some(search) = &state.search else {
+        return Ok((StatusCode::NOT_FOUND, "search isn't enabled").into_response());
+    };
+
+    let Some(query) = params.get("q").cloned() else {
+        return Ok((StatusCode::BAD_REQUEST, "expected ?q=<query>").into_response());
+    };
+
+    if let Err(error) = search.check_query(&query) {
+        return Ok((StatusCode::BAD_REQUEST, format!("bad query: {error}")).into_response());
+    }
+
+    let server = params.get("server").cloned();
+    let from = params.get("from").and_then(|from| from.parse().ok());
+    let limit = params
+        .get("limit")
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(100)
+        .clamp(1, 1000);
+
+    let results = tokio::task::spawn_blocking({
+        let state = Arc::clone(&state);
+
+        move || {
+            let search = state.search.as_ref().expect("checked above");
+
+            search.search(&SearchQuery {
+                query: &query,
+                server: server.as_deref(),
+                from,
+                limit,
+            })
+        }
+    })
+    .await
+    .map_err(eyre::Report::from)
+    .flatten()
+    .map_err(|error| {
+        error_to_response(error, StatusCode::INTERNAL_SERVER_ERROR, "couldn't search")
+    })?;
+
+    // The index only catches up every so often, so it could still have rounds it shouldn't
+    let mut released_results = Vec::with_capacity(results.len());
+    for result in results {
+        let path = state
+            .config
+            .raw_logs_path
+            .join(result.path().trim_start_matches('/'));
+
+        match state.path_is_ongoing_round(&path).await {
+            Ok(false) => released_results.push(result),
+            Ok(true) => {}
+            Err(error) => {
+                return Err(error_to_response(
+                    error,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "error figuring out if that round is ongoing or not",
+                ))
+            }
+        }
+    }
+
+    Ok(contents_response(
+        &request_headers,
+        "application/json",
+        serde_json::to_string(&released_results).expect("couldn't serialize json"),
+    ))
+}
+
+#[derive(Serialize)]
+struct FingerprintRound {
+    round_id: u64,
+    path: String,
+    count: u64,
+}
+
+// Lists the finished rounds under a folder that had the runtime with that fingerprint
+#[tracing::instrument]
+pub async fn runtime_fingerprint(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+    request_headers: HeaderMap,
+) -> Result<impl IntoResponse, Response> {
+    let (Some(fingerprint), Some(folder)) = (params.get("fingerprint"), params.get("folder"))
+    else {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "expected ?fingerprint=<fingerprint>&folder=<folder>",
+        )
+            .into_response());
+    };
+
+    if !is_fingerprint(fingerprint) {
+        return Ok((StatusCode::BAD_REQUEST, "that's not a runtime fingerprint").into_response());
+    }
+
+    let rounds = rounds_at(&state, folder).await?;
+    let fingerprint = fingerprint.to_owned();
+
+    let body = tokio::task::spawn_blocking({
+        let state = Arc::clone(&state);
+
+        move || {
+            let mut found = Vec::new();
+
+            for round in rounds {
+                let runtimes_contents =
+                    match std::fs::read_to_string(round.path.join("runtime.log")) {
+                        Ok(runtimes_contents) => runtimes_contents,
+                        Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                        Err(error) => return Err(error),
+                    };
+
+                let count = count_fingerprint(&runtimes_contents, &fingerprint);
+                if count == 0 {
+                    continue;
+                }
+
+                let link_path = round
+                    .path
+                    .strip_prefix(&state.config.raw_logs_path)
+                    .map_err(std::io::Error::other)?;
+
+                found.push(FingerprintRound {
+                    round_id: round.id,
+                    path: format!("/{}", link_path.display()),
+                    count,
+                });
+            }
+
+            serde_json::to_string(&found).map_err(std::io::Error::from)
+        }
+    })
+    .await
+    .map_err(std::io::Error::other)
+    .flatten()
+    .map_err(|error| {
+        error_to_response(
+            error,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "couldn't search runtimes",
+        )
+    })?;
+
+    Ok(contents_response(
+        &request_headers,
+        "application/json",
+        body,
+    ))
+}
+
+// A round folder on its own, or every finished round under any other folder
+async fn rounds_at(state: &AppState, path: &str) -> Result<Vec<Round>, Response> {
+    let Some(path) = resolve_query_path(state, path) else {
+        return Err((StatusCode::FORBIDDEN, "attempted path traversal").into_response());
+    };
+
+    if !std::fs::metadata(&path).is_ok_and(|metadata| metadata.is_dir()) {
+        return Err(NOT_FOUND.into_response());
+    }
+
+    let Some(id) = rounds::round_id(&path) else {
+        return folder_rounds(state, &path).await;
+    };
+
+    match state.path_is_ongoing_round(&path).await {
+        Ok(false) => Ok(vec![Round { id, path }]),
+        Ok(true) => Err(NOT_FOUND.into_response()),
+        Err(error) => Err(error_to_response(
+            error,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "error figuring out if that round is ongoing or not",
+        )),
+    }
+}
+
+// Paths in query parameters don't get normalized like the request path does
+fn resolve_query_path(state: &AppState, path: &str) -> Option<PathBuf> {
+    let relative_path = Path::new(path.trim_start_matches('/'));
+
+    if !relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        tracing::warn!("attempted path traversal: {path}");
+        return None;
+    }
+
+    Some(state.config.raw_logs_path.join(relative_path))
+}
+
+async fn folder_rounds(state: &AppState, directory: &Path) -> Result<Vec<Round>, Response> {
+    let rounds = rounds::finished_rounds(state, directory, MAX_FOLDER_ROUNDS)
+        .await
+        .map_err(|error| {
+            error_to_response(
+                error,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't find rounds",
+            )
+        })?;
+
+    if rounds.len() > MAX_FOLDER_ROUNDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("more than {MAX_FOLDER_ROUNDS} rounds in that folder, try a smaller one"),
+        )
+            .into_response());
+    }
+
+    Ok(rounds)
+}
+
+// Anywhere but the root, which is every round there is
+fn can_aggregate_runtimes(state: &AppState, directory: &Path) -> bool {
+    rounds::round_id(directory).is_none() && directory != state.config.raw_logs_path
+}
+
+fn aggregate_runtimes(
+    rounds: &[Round],
+    options: &CondenseOptions,
+) -> std::io::Result<RuntimeAggregate> {
+    let mut aggregate = RuntimeAggregate::new(options.clone());
+
+    for round in rounds {
+        match std::fs::read_to_string(round.path.join("runtime.log")) {
+            Ok(runtimes_contents) => aggregate.add_round(round.id, &runtimes_contents),
+            Err(error) if error.kind() == ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(aggregate)
+}
+
 async fn collect_traversal_items(
-    state: &AppState,
+    state: &Arc<AppState>,
     path: &std::path::Path,
 ) -> eyre::Result<Vec<TraversalItem>> {
+    // game.log/ is a folder of its categories
+    if path.is_file() {
+        return game_log_category_items(state, path).await;
+    }
+
     let mut items = vec![];
 
     let read_dir = std::fs::read_dir(path)?;
@@ -185,13 +928,43 @@ async fn collect_traversal_items(
             Err(_) => eyre::bail!("couldn't strip prefix with raw logs path"),
         };
 
-        if is_dir || get_file_sanitization_strategy(&entry_path).is_some() {
+        if is_dir || get_file_sanitization_strategy(&state.rules, &entry_path).is_some() {
             items.push(TraversalItem {
                 name: entry.file_name().to_string_lossy().into_owned(),
                 path: format!("/{}", link_path.display()),
                 is_dir,
             });
 
+            if !is_dir {
+                let report_name = format!(
+                    "{}{CENSOR_REPORT_SUFFIX}",
+                    entry.file_name().to_string_lossy()
+                );
+
+                items.push(TraversalItem {
+                    path: format!("/{}", link_path.with_file_name(&report_name).display()),
+                    name: report_name,
+                    is_dir: false,
+                });
+            }
+
+            if !is_dir && entry.file_name() == "game.log" {
+                for name in [GAME_PARSED_JSON, GAME_PARSED_NDJSON] {
+                    items.push(TraversalItem {
+                        name: name.to_string(),
+                        path: format!("/{}", link_path.with_file_name(name).display()),
+                        is_dir: false,
+                    });
+                }
+
+                // Its categories are only found once someone opens it
+                items.push(TraversalItem {
+                    name: "game.log".to_owned(),
+                    path: format!("/{}/", link_path.display()),
+                    is_dir: true,
+                });
+            }
+
             // add fake runtime condensed links
             if !is_dir
                 && entry_path
@@ -199,34 +972,215 @@ async fn collect_traversal_items(
                     .map(|s| s == "runtime")
                     .unwrap_or(false)
             {
-                items.push(TraversalItem {
-                    name: RUNTIME_CONDENSED_JSON.to_string(),
-                    path: format!(
-                        "/{}",
-                        link_path.with_file_name(RUNTIME_CONDENSED_JSON).display()
-                    ),
-                    is_dir: false,
-                });
-                items.push(TraversalItem {
-                    name: RUNTIME_CONDENSED_TXT.to_string(),
-                    path: format!(
-                        "/{}",
-                        link_path.with_file_name(RUNTIME_CONDENSED_TXT).display()
-                    ),
-                    is_dir: false,
-                });
+                for name in RUNTIME_PRETEND_FILES {
+                    items.push(TraversalItem {
+                        name: name.to_string(),
+                        path: format!("/{}", link_path.with_file_name(name).display()),
+                        is_dir: false,
+                    });
+                }
             }
         }
     }
 
+    let relative_path = path.strip_prefix(&state.config.raw_logs_path)?;
+    let generated_names: &[&str] = if rounds::round_id(path).is_some() {
+        &[ROUND_SUMMARY_JSON, ROUND_SUMMARY_HTML]
+    } else if can_aggregate_runtimes(state, path) {
+        &[RUNTIME_AGGREGATE_JSON]
+    } else {
+        &[]
+    };
+
+    for name in generated_names {
+        items.push(TraversalItem {
+            name: name.to_string(),
+            path: format!("/{}", relative_path.join(name).display()),
+            is_dir: false,
+        });
+    }
+
     items.sort_by(|a, b| (b.is_dir, &a.name).cmp(&(a.is_dir, &b.name)));
 
     Ok(items)
 }
 
-fn headers(content_type: &str) -> [(&'static str, &str); 2] {
+// Finding them means reading the whole log, so they're only listed when game.log/ itself is
+async fn game_log_category_items(
+    state: &Arc<AppState>,
+    game_log: &std::path::Path,
+) -> eyre::Result<Vec<TraversalItem>> {
+    let link_path = game_log.strip_prefix(&state.config.raw_logs_path)?;
+    let file = std::fs::File::open(game_log)?;
+
+    let output = complete_output(
+        Arc::clone(state),
+        game_log.to_owned(),
+        "game.log categories".to_owned(),
+        move |state, writer| {
+            for category in game::categories(&state.rules, std::io::BufReader::new(file))? {
+                writeln!(writer, "{category}")?;
+            }
+
+            Ok(())
+        },
+    )
+    .await?;
+
+    Ok(String::from_utf8_lossy(&output.into_bytes()?)
+        .lines()
+        .map(|category| {
+            let name = format!("{category}.log");
+
+            TraversalItem {
+                path: format!("/{}", link_path.join(&name).display()),
+                name,
+                is_dir: false,
+            }
+        })
+        .collect())
+}
+
+// Anything other than the source file that changes the output. Always part of the ETag,
+// but only part of a cache key when it can't be made up of arbitrary query values.
+enum Variant {
+    Cached(String),
+    Uncached(String),
+}
+
+async fn sanitized_file_response<F>(
+    state: Arc<AppState>,
+    request_headers: &HeaderMap,
+    source_path: PathBuf,
+    variant: Variant,
+    content_type: &str,
+    cache_control: &'static str,
+    write_fn: F,
+) -> Response
+where
+    F: FnOnce(&AppState, &mut dyn Write) -> std::io::Result<()> + Send + 'static,
+{
+    let (variant, cacheable) = match variant {
+        Variant::Cached(variant) => (variant, true),
+        Variant::Uncached(variant) => (variant, false),
+    };
+
+    let validators =
+        match Validators::for_source(state.rules.policy_version(), &source_path, &variant) {
+            Ok(validators) => validators,
+            Err(error) => {
+                return error_to_response(
+                    error,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "couldn't get metadata of file",
+                )
+            }
+        };
+
+    // Ranges are taken from the cached output. Without one they'd mean sanitizing all of it
+    // into memory first, so everyone gets the whole thing streamed instead.
+    let can_serve_ranges = cacheable && state.cache.is_some();
+
+    let evaluation = match validators.evaluate(request_headers) {
+        Evaluation::Partial(_) if !can_serve_ranges => Evaluation::Full,
+        evaluation => evaluation,
+    };
+
+    match evaluation {
+        Evaluation::NotModified => (
+            StatusCode::NOT_MODIFIED,
+            [("cache-control", cache_control)],
+            validators,
+            (),
+        )
+            .into_response(),
+
+        Evaluation::Full => {
+            let (encoding, body) = if cacheable {
+                stream_cached_body(state, request_headers, source_path, variant, write_fn)
+            } else {
+                stream_uncached_body(state, write_fn)
+            };
+
+            (
+                StatusCode::OK,
+                headers(cache_control, content_type),
+                [(
+                    "accept-ranges",
+                    if can_serve_ranges { "bytes" } else { "none" },
+                )],
+                validators,
+                encoding,
+                body,
+            )
+                .into_response()
+        }
+
+        Evaluation::Partial(range) => {
+            let output = match complete_output(state, source_path, variant, write_fn).await {
+                Ok(output) => output,
+                Err(error) => {
+                    return error_to_response(
+                        error,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "couldn't sanitize file",
+                    )
+                }
+            };
+
+            let length = output.length;
+            let Some(range) = range.resolve(length) else {
+                return (
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [("content-range", format!("bytes */{length}"))],
+                    validators,
+                    (),
+                )
+                    .into_response();
+            };
+
+            (
+                StatusCode::PARTIAL_CONTENT,
+                headers(cache_control, content_type),
+                [(
+                    "content-range",
+                    format!("bytes {}-{}/{length}", range.start(), range.end()),
+                )],
+                validators,
+                output.into_body(range),
+            )
+                .into_response()
+        }
+    }
+}
+
+// For responses built in full that can change, like directory listings.
+// Hashing them means polling clients get a 304 until something actually shows up.
+fn contents_response(request_headers: &HeaderMap, content_type: &str, body: String) -> Response {
+    let validators = Validators::for_contents(body.as_bytes());
+
+    if validators.evaluate(request_headers) == Evaluation::NotModified {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [("cache-control", DIRECTORY_CACHE_CONTROL)],
+            validators,
+            (),
+        )
+            .into_response();
+    }
+
+    (
+        StatusCode::OK,
+        headers(DIRECTORY_CACHE_CONTROL, content_type),
+        validators,
+        body,
+    )
+        .into_response()
+}
+
+fn headers<'a>(cache_control: &'static str, content_type: &'a str) -> [(&'static str, &'a str); 2] {
     [
-        ("cache-control", "public, max-age=31536000"),
+        ("cache-control", cache_control),
         ("content-type", content_type),
     ]
 }
@@ -235,7 +1189,7 @@ fn error_to_response(
     error: impl std::fmt::Debug,
     status_code: StatusCode,
     message: &'static str,
-) -> axum::response::Response {
+) -> Response {
     tracing::error!("{message}: {error:?}");
     (
         status_code,
@@ -246,7 +1200,7 @@ fn error_to_response(
         .into_response()
 }
 
-async fn traversal_page(state: &AppState, path: &std::path::Path) -> eyre::Result<String> {
+async fn traversal_page(state: &Arc<AppState>, path: &std::path::Path) -> eyre::Result<String> {
     let items = collect_traversal_items(state, path).await?;
 
     let list_html: String = items
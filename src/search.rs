@@ -0,0 +1,530 @@
+use std::{
+    collections::HashMap,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use eyre::Context;
+use tantivy::{
+    collector::TopDocs,
+    directory::MmapDirectory,
+    query::{BooleanQuery, Occur, Query, QueryParser, QueryParserError, RangeQuery, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT},
+    snippet::SnippetGenerator,
+    Index, IndexReader, IndexWriter, TantivyDocument, Term,
+};
+
+use crate::{
+    app_state::AppState,
+    parsers::{
+        censor_report::CensorReport, get_file_sanitization_strategy, rules::Rules, SanitizeOptions,
+        Strategy,
+    },
+    rounds::{self, Round},
+};
+
+const MANIFEST_FILE_NAME: &str = "indexed-rounds.json";
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SearchConfig {
+    path: PathBuf,
+
+    // How often to look for newly released rounds
+    #[serde(default = "default_refresh_interval_seconds")]
+    refresh_interval_seconds: u64,
+}
+
+fn default_refresh_interval_seconds() -> u64 {
+    300
+}
+
+// Every line of the sanitized game.log and pass-through logs of finished rounds, one document each.
+// Only ever sees sanitized output, so it can't be used to find anything the logs don't show.
+pub struct SearchIndex {
+    config: SearchConfig,
+    policy_version: String,
+
+    index: Index,
+    reader: IndexReader,
+    writer: parking_lot::Mutex<IndexWriter>,
+    fields: Fields,
+}
+
+impl std::fmt::Debug for SearchIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchIndex")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    // The first folder under the raw logs path
+    server: Field,
+    round_id: Field,
+    // The round folder, like "/sybil/2023/11/01/round-100", for removing a round's lines
+    round_path: Field,
+    // Like "/sybil/2023/11/01/round-100/game.log"
+    path: Field,
+    // Starting at 1
+    line: Field,
+    text: Field,
+}
+
+// What's in the index, so only rounds that changed get indexed again
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct Manifest {
+    // Everything is indexed again when the policy changes
+    policy_version: String,
+    // Round path -> fingerprint of its files
+    rounds: HashMap<String, String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SearchResult {
+    round_id: u64,
+    server: String,
+    path: String,
+    line: u64,
+    snippet: String,
+    // Byte ranges of the snippet that matched
+    highlighted: Vec<[usize; 2]>,
+}
+
+impl SearchResult {
+    // Like "/sybil/2023/11/01/round-100/game.log"
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+pub struct SearchQuery<'a> {
+    pub query: &'a str,
+    pub server: Option<&'a str>,
+    // Minimum round ID
+    pub from: Option<u64>,
+    pub limit: usize,
+}
+
+impl SearchIndex {
+    pub fn new(config: SearchConfig, policy_version: &str) -> eyre::Result<Self> {
+        std::fs::create_dir_all(&config.path)
+            .with_context(|| format!("creating {}", config.path.display()))?;
+
+        let mut schema = Schema::builder();
+        let fields = Fields {
+            server: schema.add_text_field("server", STRING | STORED),
+            round_id: schema.add_u64_field("round_id", INDEXED | STORED | FAST),
+            round_path: schema.add_text_field("round_path", STRING),
+            path: schema.add_text_field("path", STRING | STORED),
+            line: schema.add_u64_field("line", STORED),
+            text: schema.add_text_field("text", TEXT | STORED),
+        };
+
+        let index = Index::open_or_create(MmapDirectory::open(&config.path)?, schema.build())
+            .context("opening search index")?;
+        let reader = index.reader()?;
+        let writer = index.writer(50_000_000)?;
+
+        Ok(Self {
+            config,
+            policy_version: policy_version.to_owned(),
+            index,
+            reader,
+            writer: parking_lot::Mutex::new(writer),
+            fields,
+        })
+    }
+
+    fn parse_query(&self, query: &str) -> Result<Box<dyn Query>, QueryParserError> {
+        QueryParser::for_index(&self.index, vec![self.fields.text]).parse_query(query)
+    }
+
+    // So bad queries can be told apart from the index breaking
+    pub fn check_query(&self, query: &str) -> Result<(), QueryParserError> {
+        self.parse_query(query).map(|_| ())
+    }
+
+    pub fn search(&self, search_query: &SearchQuery) -> eyre::Result<Vec<SearchResult>> {
+        let text_query = self.parse_query(search_query.query)?;
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, text_query.box_clone())];
+
+        if let Some(server) = search_query.server {
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(self.fields.server, server),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        }
+
+        if let Some(from) = search_query.from {
+            clauses.push((
+                Occur::Must,
+                Box::new(RangeQuery::new(
+                    Bound::Included(Term::from_field_u64(self.fields.round_id, from)),
+                    Bound::Unbounded,
+                )),
+            ));
+        }
+
+        let query = BooleanQuery::new(clauses);
+        let searcher = self.reader.searcher();
+        let snippets = SnippetGenerator::create(&searcher, &*text_query, self.fields.text)?;
+
+        let mut results = Vec::new();
+        for (_, address) in searcher.search(&query, &TopDocs::with_limit(search_query.limit))? {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let snippet = snippets.snippet_from_doc(&document);
+
+            let text = |field| {
+                document
+                    .get_first(field)
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default()
+                    .to_owned()
+            };
+
+            let number = |field| {
+                document
+                    .get_first(field)
+                    .and_then(|value| value.as_u64())
+                    .unwrap_or_default()
+            };
+
+            results.push(SearchResult {
+                round_id: number(self.fields.round_id),
+                server: text(self.fields.server),
+                path: text(self.fields.path),
+                line: number(self.fields.line),
+                snippet: snippet.fragment().to_owned(),
+                highlighted: snippet
+                    .highlighted()
+                    .iter()
+                    .map(|range| [range.start, range.end])
+                    .collect(),
+            });
+        }
+
+        Ok(results)
+    }
+
+    // Indexes every finished round that isn't indexed yet, or changed since it was
+    fn refresh(&self, raw_logs_path: &Path, rules: &Rules, rounds: &[Round]) -> eyre::Result<()> {
+        let manifest_path = self.config.path.join(MANIFEST_FILE_NAME);
+        let mut manifest: Manifest = match std::fs::read(&manifest_path) {
+            Ok(manifest) => serde_json::from_slice(&manifest).context("reading manifest")?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Manifest::default(),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut writer = self.writer.lock();
+
+        if manifest.policy_version != self.policy_version {
+            tracing::info!(
+                "indexing everything again for policy {}",
+                self.policy_version
+            );
+            writer.delete_all_documents()?;
+            manifest = Manifest {
+                policy_version: self.policy_version.clone(),
+                rounds: HashMap::new(),
+            };
+        }
+
+        let mut rounds_left = manifest.rounds.clone();
+        let mut changed = manifest.rounds.is_empty();
+
+        for round in rounds {
+            let round_path = link_path(raw_logs_path, &round.path)?;
+            let files = searchable_files(rules, &round.path)?;
+            let fingerprint =
+                rounds::files_fingerprint(files.iter().map(|(path, _)| path.clone()))?;
+
+            if rounds_left.remove(&round_path).as_ref() == Some(&fingerprint) {
+                continue;
+            }
+
+            tracing::debug!("indexing {round_path}");
+            writer.delete_term(Term::from_field_text(self.fields.round_path, &round_path));
+
+            let server = round_path
+                .trim_start_matches('/')
+                .split('/')
+                .next()
+                .unwrap_or_default();
+
+            for (file_path, strategy) in files {
+                let path = link_path(raw_logs_path, &file_path)?;
+                let mut number = 0;
+
+                let mut lines = LineWriter::new(|line| {
+                    number += 1;
+                    if line.trim().is_empty() {
+                        return Ok(());
+                    }
+
+                    let mut document = TantivyDocument::new();
+                    document.add_text(self.fields.server, server);
+                    document.add_u64(self.fields.round_id, round.id);
+                    document.add_text(self.fields.round_path, &round_path);
+                    document.add_text(self.fields.path, &path);
+                    document.add_u64(self.fields.line, number);
+                    document.add_text(self.fields.text, line);
+                    writer
+                        .add_document(document)
+                        .map(|_| ())
+                        .map_err(std::io::Error::other)
+                });
+
+                strategy.sanitize(
+                    rules,
+                    &SanitizeOptions::default(),
+                    std::io::BufReader::new(std::fs::File::open(&file_path)?),
+                    &mut lines,
+                    &mut CensorReport::new(strategy),
+                )?;
+                lines.finish()?;
+            }
+
+            manifest.rounds.insert(round_path, fingerprint);
+            changed = true;
+        }
+
+        // Rounds that were deleted
+        for round_path in rounds_left.into_keys() {
+            writer.delete_term(Term::from_field_text(self.fields.round_path, &round_path));
+            manifest.rounds.remove(&round_path);
+            changed = true;
+        }
+
+        if !changed {
+            return Ok(());
+        }
+
+        writer.commit()?;
+        self.reader.reload()?;
+
+        // Only after the commit, so a crash means indexing the rounds again rather than missing them
+        let temporary_path = manifest_path.with_extension("tmp");
+        std::fs::write(&temporary_path, serde_json::to_vec(&manifest)?)?;
+        std::fs::rename(temporary_path, manifest_path)?;
+
+        tracing::info!("search index has {} rounds", manifest.rounds.len());
+
+        Ok(())
+    }
+}
+
+// Hands over the sanitized output a line at a time, so no more than a line of it is in memory
+struct LineWriter<F: FnMut(&str) -> std::io::Result<()>> {
+    line: Vec<u8>,
+    line_fn: F,
+}
+
+impl<F: FnMut(&str) -> std::io::Result<()>> LineWriter<F> {
+    fn new(line_fn: F) -> Self {
+        Self {
+            line: Vec::new(),
+            line_fn,
+        }
+    }
+
+    fn end_line(&mut self) -> std::io::Result<()> {
+        if self.line.last() == Some(&b'\r') {
+            self.line.pop();
+        }
+
+        (self.line_fn)(&String::from_utf8_lossy(&self.line))?;
+        self.line.clear();
+        Ok(())
+    }
+
+    // The last line, if it didn't end in a newline
+    fn finish(mut self) -> std::io::Result<()> {
+        if !self.line.is_empty() {
+            self.end_line()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&str) -> std::io::Result<()>> std::io::Write for LineWriter<F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut rest = buf;
+
+        while let Some(end) = rest.iter().position(|&byte| byte == b'\n') {
+            self.line.extend_from_slice(&rest[..end]);
+            self.end_line()?;
+            rest = &rest[end + 1..];
+        }
+
+        self.line.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Looks for released rounds forever
+pub async fn refresh_loop(state: Arc<AppState>) {
+    let Some(search) = &state.search else {
+        return;
+    };
+
+    let interval = Duration::from_secs(search.config.refresh_interval_seconds);
+
+    loop {
+        if let Err(error) = refresh(&state).await {
+            tracing::error!("couldn't refresh search index: {error:?}");
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+async fn refresh(state: &Arc<AppState>) -> eyre::Result<()> {
+    // Never includes ongoing rounds
+    let rounds = rounds::finished_rounds(state, &state.config.raw_logs_path, usize::MAX).await?;
+
+    let state = Arc::clone(state);
+    tokio::task::spawn_blocking(move || {
+        let search = state.search.as_ref().expect("refreshing without search");
+        search.refresh(&state.config.raw_logs_path, &state.rules, &rounds)
+    })
+    .await?
+}
+
+// game.log and the pass-through logs anywhere in the round folder
+fn searchable_files(rules: &Rules, round_path: &Path) -> std::io::Result<Vec<(PathBuf, Strategy)>> {
+    let mut files = Vec::new();
+    let mut directories = vec![round_path.to_owned()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if entry.file_type()?.is_dir() {
+                directories.push(path);
+                continue;
+            }
+
+            if let Some(strategy @ (Strategy::Game | Strategy::PassThrough)) =
+                get_file_sanitization_strategy(rules, &path)
+            {
+                files.push((path, strategy));
+            }
+        }
+    }
+
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(files)
+}
+
+fn link_path(raw_logs_path: &Path, path: &Path) -> eyre::Result<String> {
+    let link_path = path
+        .strip_prefix(raw_logs_path)
+        .context("couldn't strip prefix with raw logs path")?;
+
+    Ok(format!("/{}", link_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_index() {
+        let directory = std::env::temp_dir().join(format!("search-test-{}", std::process::id()));
+        let raw_logs_path = directory.join("logs");
+
+        let round = |id: u64, game_log: &str| {
+            let path = raw_logs_path.join(format!("sybil/round-{id}"));
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("game.log"), game_log).unwrap();
+            Round { id, path }
+        };
+
+        let rounds = [
+            round(
+                5,
+                "[12:00:00] SAY: hello there\n[12:00:01] ADMINPRIVATE: hello secret\n",
+            ),
+            round(6, "[12:00:00] EMOTE: waves\n[12:00:01] SAY: hello again\n"),
+        ];
+
+        let rules = Rules::default();
+        let search = SearchIndex::new(
+            SearchConfig {
+                path: directory.join("index"),
+                refresh_interval_seconds: 300,
+            },
+            "policy",
+        )
+        .unwrap();
+        search.refresh(&raw_logs_path, &rules, &rounds).unwrap();
+
+        let found = |query: &str, from: Option<u64>| {
+            let mut found = search
+                .search(&SearchQuery {
+                    query,
+                    server: Some("sybil"),
+                    from,
+                    limit: 10,
+                })
+                .unwrap()
+                .into_iter()
+                .map(|result| (result.round_id, result.path, result.line))
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+
+        assert_eq!(
+            found("hello", None),
+            [
+                (5, "/sybil/round-5/game.log".to_owned(), 1),
+                (6, "/sybil/round-6/game.log".to_owned(), 2),
+            ]
+        );
+        assert_eq!(found("hello", Some(6)).len(), 1);
+
+        // Censored lines are only indexed as what replaced them
+        assert!(found("secret", None).is_empty());
+
+        // Rounds that are gone are removed
+        search
+            .refresh(&raw_logs_path, &rules, &rounds[..1])
+            .unwrap();
+        assert_eq!(found("hello", None).len(), 1);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_line_writer() {
+        use std::io::Write;
+
+        let mut lines = Vec::new();
+        let mut writer = LineWriter::new(|line| {
+            lines.push(line.to_owned());
+            Ok(())
+        });
+
+        writer.write_all(b"first\r\nsec").unwrap();
+        writer.write_all(b"ond\n\nlast").unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(lines, ["first", "second", "", "last"]);
+    }
+}
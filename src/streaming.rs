@@ -0,0 +1,65 @@
+use std::io::{self, Write};
+
+use axum::body::{Body, Bytes};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+// How much output to buffer before sending it down to the client
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// How many chunks can be waiting to be sent before the writer blocks
+const CHANNEL_CAPACITY: usize = 4;
+
+// Runs the writing function on a blocking thread, streaming whatever it writes into the body.
+// Memory use is bounded by the chunk size and channel capacity, not by how much gets written.
+pub fn stream_body<F>(write_fn: F) -> Body
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let mut writer = io::BufWriter::with_capacity(
+            CHUNK_SIZE,
+            ChannelWriter {
+                sender: sender.clone(),
+            },
+        );
+
+        let result = write_fn(&mut writer).and_then(|()| writer.flush());
+
+        match result {
+            Ok(()) => {}
+
+            // The client went away, nobody to tell
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => {}
+
+            Err(error) => {
+                tracing::error!("error while streaming body: {error:?}");
+
+                // Makes the body error out instead of looking like it finished
+                let _ = sender.blocking_send(Err(error));
+            }
+        }
+    });
+
+    Body::from_stream(ReceiverStream::new(receiver))
+}
+
+struct ChannelWriter {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}